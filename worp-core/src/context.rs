@@ -0,0 +1,126 @@
+use crate::model::game::{token::Token, Game};
+use std::collections::BTreeMap;
+use worp_scroll::runtime::{EvaluationContext, Table, Value};
+
+/// Resolves the pre-defined tables of macros from a game, with `self` being the token the macro is run for,
+/// so the same macro can be run against any token on a map.
+pub struct GameContext<'a> {
+    game: &'a Game,
+    token: Option<&'a Token>,
+    targets: Vec<&'a Token>,
+}
+
+impl<'a> GameContext<'a> {
+    pub fn new(game: &'a Game) -> Self {
+        Self {
+            game,
+            token: None,
+            targets: Vec::new(),
+        }
+    }
+
+    pub fn with_token(mut self, token: &'a Token) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Targets the given tokens, such as the tokens a player has selected with [`Game::selected_tokens`].
+    pub fn with_targets(mut self, targets: impl IntoIterator<Item = &'a Token>) -> Self {
+        self.targets = targets.into_iter().collect();
+        self
+    }
+}
+
+impl EvaluationContext for GameContext<'_> {
+    fn global_table(&self) -> Value {
+        Value::Table(Table::StringKeyed(self.game.global().clone()))
+    }
+
+    fn self_table(&self) -> Option<Value> {
+        self.token.map(token_table)
+    }
+
+    fn target_tables(&self) -> Vec<Value> {
+        self.targets.iter().copied().map(token_table).collect()
+    }
+}
+
+/// Converts a token into a table of its `id` and its attributes.
+/// Attributes with whole values become integers, so they can be used as dice counts and table keys.
+fn token_table(token: &Token) -> Value {
+    let mut table = token
+        .attributes()
+        .iter()
+        .map(|(name, value)| (name.clone(), attribute_value(*value)))
+        .collect::<BTreeMap<_, _>>();
+    table.insert("id".to_owned(), Value::String(token.id().to_owned()));
+
+    Value::Table(Table::StringKeyed(table))
+}
+
+fn attribute_value(value: f64) -> Value {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        Value::Integer(value as i64)
+    } else {
+        Value::Decimal(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token(id: &str, attributes: &str) -> Token {
+        let input = format!(
+            "id = \"{}\"\nmacro_hotbar = []\nmacros = []\n\n[attributes]\n{}",
+            id, attributes
+        );
+        toml::from_str(&input).unwrap()
+    }
+
+    #[test]
+    fn token_tables_convert_attributes() {
+        let table = token_table(&token("gimli", "hp = 12.0\nspeed = 7.5\nac = -1.0"));
+        let expected = vec![
+            ("ac".to_owned(), Value::Integer(-1)),
+            ("hp".to_owned(), Value::Integer(12)),
+            ("id".to_owned(), Value::String("gimli".to_owned())),
+            ("speed".to_owned(), Value::Decimal(7.5)),
+        ];
+
+        assert_eq!(table, Value::Table(Table::StringKeyed(expected.into_iter().collect())));
+        assert_eq!(attribute_value(1e300), Value::Decimal(1e300));
+    }
+
+    #[test]
+    fn token_tables_keep_their_id() {
+        // The `id` identifies the token for attribute changes, so it replaces an attribute with the same name.
+        let table = token_table(&token("gimli", "id = 3.0"));
+        let expected = vec![("id".to_owned(), Value::String("gimli".to_owned()))];
+
+        assert_eq!(table, Value::Table(Table::StringKeyed(expected.into_iter().collect())));
+    }
+
+    #[test]
+    fn context_resolves_tables_from_game() {
+        let game: Game = toml::from_str("[maps]\n\n[players]\n\n[global]\ncritical = true\n").unwrap();
+        let gimli = token("gimli", "hp = 12.0");
+        let legolas = token("legolas", "hp = 10.0");
+        let orc = token("orc", "hp = 15.0");
+
+        let context = GameContext::new(&game);
+        assert_eq!(context.self_table(), None);
+        assert!(context.target_tables().is_empty());
+
+        let context = GameContext::new(&game)
+            .with_token(&gimli)
+            .with_targets(vec![&orc, &legolas]);
+        let expected = vec![("critical".to_owned(), Value::Boolean(true))];
+        assert_eq!(
+            context.global_table(),
+            Value::Table(Table::StringKeyed(expected.into_iter().collect()))
+        );
+        assert_eq!(context.self_table(), Some(token_table(&gimli)));
+        assert_eq!(context.target_tables(), vec![token_table(&orc), token_table(&legolas)]);
+    }
+}
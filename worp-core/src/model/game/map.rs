@@ -1,5 +1,5 @@
 use super::{
-    player::PlayerTokenKey,
+    player::{PlayerKey, PlayerTokenKey},
     token::{TokenKey, TokenPosition},
 };
 use serde::{Deserialize, Serialize};
@@ -19,4 +19,105 @@ pub struct Map {
     map_type: MapType,
     player_tokens: BTreeMap<PlayerTokenKey, TokenPosition>,
     tokens: BTreeMap<TokenKey, TokenPosition>,
+    /// The tokens each player has selected on the map, in the order they were selected,
+    /// which become the targets of the macros the player runs.
+    #[serde(default, serialize_with = "toml::ser::tables_last")]
+    selected_tokens: BTreeMap<PlayerKey, Vec<PlayerTokenKey>>,
+}
+
+impl Map {
+    pub fn new(id: impl Into<String>, map_type: MapType) -> Self {
+        Self {
+            id: id.into(),
+            map_type,
+            player_tokens: BTreeMap::new(),
+            tokens: BTreeMap::new(),
+            selected_tokens: BTreeMap::new(),
+        }
+    }
+
+    /// Places a player's token on the map, or moves it if it's already on the map.
+    pub fn place_player_token(&mut self, token: PlayerTokenKey, position: TokenPosition) {
+        self.player_tokens.insert(token, position);
+    }
+
+    /// Replaces the tokens the player has selected, with an empty selection clearing it.
+    /// Only tokens placed on the map can be selected, otherwise the selection is left unchanged.
+    pub fn select_tokens(
+        &mut self,
+        player: PlayerKey,
+        tokens: impl IntoIterator<Item = PlayerTokenKey>,
+    ) -> Result<(), SelectionError> {
+        let tokens: Vec<_> = tokens.into_iter().collect();
+
+        if let Some(token) = tokens.iter().find(|token| !self.player_tokens.contains_key(token)) {
+            return Err(SelectionError::TokenNotOnMap(token.clone()));
+        }
+
+        if tokens.is_empty() {
+            self.selected_tokens.remove(&player);
+        } else {
+            self.selected_tokens.insert(player, tokens);
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn selected_tokens(&self, player: &PlayerKey) -> &[PlayerTokenKey] {
+        self.selected_tokens.get(player).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// The reasons tokens can't be selected on a map.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum SelectionError {
+    #[error("Cannot select token {0:?}, as it isn't on the map.")]
+    TokenNotOnMap(PlayerTokenKey),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token(player: &str, token: &str) -> PlayerTokenKey {
+        PlayerTokenKey::new(player.into(), token.into())
+    }
+
+    #[test]
+    fn select_tokens_in_order() {
+        let mut map = Map::new("dungeon", MapType::Square);
+        let player = PlayerKey::from("alice");
+        for (index, key) in [token("alice", "gimli"), token("bob", "troll"), token("bob", "orc")]
+            .iter()
+            .enumerate()
+        {
+            map.place_player_token(key.clone(), TokenPosition(index as u64, 0));
+        }
+
+        map.select_tokens(player.clone(), vec![token("bob", "orc"), token("bob", "troll")])
+            .unwrap();
+        assert_eq!(
+            map.selected_tokens(&player),
+            [token("bob", "orc"), token("bob", "troll")]
+        );
+        assert!(map.selected_tokens(&PlayerKey::from("bob")).is_empty());
+
+        map.select_tokens(player.clone(), Vec::new()).unwrap();
+        assert!(map.selected_tokens(&player).is_empty());
+    }
+
+    #[test]
+    fn select_tokens_rejects_missing_tokens() {
+        let mut map = Map::new("dungeon", MapType::Square);
+        let player = PlayerKey::from("alice");
+        map.place_player_token(token("bob", "troll"), TokenPosition(0, 0));
+        map.select_tokens(player.clone(), vec![token("bob", "troll")]).unwrap();
+
+        assert_eq!(
+            map.select_tokens(player.clone(), vec![token("bob", "troll"), token("bob", "dragon")]),
+            Err(SelectionError::TokenNotOnMap(token("bob", "dragon")))
+        );
+        assert_eq!(map.selected_tokens(&player), [token("bob", "troll")]);
+    }
 }
@@ -7,6 +7,7 @@ use player::{Player, PlayerKey, PlayerTokenKey};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use token::Token;
+use worp_scroll::runtime::Value;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Game {
@@ -14,10 +15,105 @@ pub struct Game {
     maps: BTreeMap<map::MapKey, map::Map>,
     #[serde(serialize_with = "toml::ser::tables_last")]
     players: BTreeMap<PlayerKey, Player>,
+    /// Game-wide data that macros can reference through the `global` table, such as tables of ability modifiers.
+    #[serde(default, serialize_with = "toml::ser::tables_last")]
+    global: BTreeMap<String, Value>,
 }
 
 impl Game {
     pub fn player_token(&self, key: &PlayerTokenKey) -> Option<&Token> {
         self.players.get(&key.player)?.token(&key.token)
     }
+
+    /// The tokens the player has selected on their current map, which are the targets of the macros they run.
+    /// Selected tokens that no longer exist are skipped.
+    pub fn selected_tokens(&self, player: &PlayerKey) -> Vec<&Token> {
+        let selected = self
+            .players
+            .get(player)
+            .and_then(Player::current_map)
+            .and_then(|map| self.maps.get(map))
+            .map(|map| map.selected_tokens(player))
+            .unwrap_or_default();
+
+        selected.iter().filter_map(|key| self.player_token(key)).collect()
+    }
+
+    #[inline]
+    pub fn global(&self) -> &BTreeMap<String, Value> {
+        &self.global
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::{Map, MapType};
+    use token::TokenPosition;
+    use worp_scroll::runtime::Table;
+
+    #[test]
+    fn global_round_trips_through_toml() {
+        let input = "[maps]\n\n[players]\n\n\
+                     [global]\n\
+                     critical = true\n\
+                     name = \"Dungeon\"\n\
+                     scale = 1.5\n\n\
+                     [global.ability_mods]\n\
+                     10 = 0\n\
+                     12 = 1\n\n\
+                     [global.sizes]\n\
+                     small = 1\n\
+                     large = 2\n";
+        let game: Game = toml::from_str(input).unwrap();
+
+        let ability_mods = vec![(10, Value::Integer(0)), (12, Value::Integer(1))];
+        assert_eq!(game.global()["critical"], Value::Boolean(true));
+        assert_eq!(game.global()["name"], Value::String("Dungeon".to_owned()));
+        assert_eq!(game.global()["scale"], Value::Decimal(1.5));
+        assert_eq!(
+            game.global()["ability_mods"],
+            Value::Table(Table::IntegerKeyed(ability_mods.into_iter().collect()))
+        );
+        assert!(matches!(game.global()["sizes"], Value::Table(Table::StringKeyed(_))));
+
+        let output = toml::to_string(&game).unwrap();
+        let round_tripped: Game = toml::from_str(&output).unwrap();
+        assert_eq!(round_tripped.global(), game.global());
+    }
+
+    #[test]
+    fn selected_tokens_follow_the_current_map() {
+        let mut alice = Player::new("alice", vec![Token::new("gimli")]);
+        let bob = Player::new("bob", vec![Token::new("troll"), Token::new("orc")]);
+        let mut map = Map::new("dungeon", MapType::Square);
+        let selection = vec![
+            PlayerTokenKey::new("bob".into(), "orc".into()),
+            PlayerTokenKey::new("bob".into(), "goblin".into()),
+            PlayerTokenKey::new("alice".into(), "gimli".into()),
+        ];
+        for key in &selection {
+            map.place_player_token(key.clone(), TokenPosition(0, 0));
+        }
+        map.select_tokens("alice".into(), selection).unwrap();
+
+        let mut game = Game {
+            maps: vec![("dungeon".into(), map)].into_iter().collect(),
+            players: BTreeMap::new(),
+            global: BTreeMap::new(),
+        };
+        game.players.insert("bob".into(), bob);
+        game.players.insert("alice".into(), Player::new("alice", Vec::new()));
+        assert!(game.selected_tokens(&"alice".into()).is_empty());
+
+        // Selected tokens that no longer exist, such as the goblin, are skipped.
+        alice.set_current_map(Some("dungeon".into()));
+        game.players.insert("alice".into(), alice);
+        let selected: Vec<_> = game
+            .selected_tokens(&"alice".into())
+            .iter()
+            .map(|token| token.id())
+            .collect();
+        assert_eq!(selected, ["orc", "gimli"]);
+    }
 }
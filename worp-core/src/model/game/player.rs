@@ -32,7 +32,17 @@ impl Player {
 impl Player {
     #[inline]
     pub fn token(&self, key: &TokenKey) -> Option<&Token> {
-        self.owned_tokens.get(&key)
+        self.owned_tokens.get(key)
+    }
+
+    #[inline]
+    pub fn current_map(&self) -> Option<&MapKey> {
+        self.current_map.as_ref()
+    }
+
+    #[inline]
+    pub fn set_current_map(&mut self, map: Option<MapKey>) {
+        self.current_map = map;
     }
 }
 
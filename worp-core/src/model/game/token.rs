@@ -1,6 +1,7 @@
 use super::action::{MacroHotbar, MacroKey};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use worp_scroll::{error::EvaluationError, runtime::AttributeChange};
 
 key!(Token::id as TokenKey: String);
 
@@ -22,7 +23,124 @@ impl Token {
             macros: Vec::new(),
         }
     }
+
+    #[inline]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[inline]
+    pub fn attributes(&self) -> &BTreeMap<String, f64> {
+        &self.attributes
+    }
+
+    /// Applies a change made to one of the token's attributes by a macro, returning the attribute's previous value.
+    /// Only numbers can be stored as attributes.
+    pub fn apply_change(&mut self, change: &AttributeChange) -> Result<Option<f64>, ChangeError> {
+        self.check_token(change)?;
+        let value = change.value.as_decimal()?;
+
+        Ok(self.attributes.insert(change.attribute.clone(), value))
+    }
+
+    /// Undoes a change that was applied to the token, restoring the attribute's previous value,
+    /// or removing the attribute if the token didn't have it before.
+    pub fn undo_change(&mut self, change: &AttributeChange) -> Result<(), ChangeError> {
+        self.check_token(change)?;
+
+        match &change.previous {
+            Some(previous) => {
+                self.attributes.insert(change.attribute.clone(), previous.as_decimal()?);
+            }
+            None => {
+                self.attributes.remove(&change.attribute);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_token(&self, change: &AttributeChange) -> Result<(), ChangeError> {
+        if change.token != self.id {
+            return Err(ChangeError::WrongToken {
+                token: self.id.clone(),
+                changed: change.token.clone(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The reasons a change made by a macro can't be applied to a token.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ChangeError {
+    #[error("Cannot apply a change to token `{changed}` to token `{token}`.")]
+    WrongToken { token: String, changed: String },
+    #[error(transparent)]
+    InvalidValue(#[from] EvaluationError),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenPosition(pub u64, pub u64);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use worp_scroll::runtime::Value;
+
+    fn change(token: &str, attribute: &str, previous: Option<Value>, value: Value) -> AttributeChange {
+        AttributeChange {
+            token: token.to_owned(),
+            attribute: attribute.to_owned(),
+            previous,
+            value,
+        }
+    }
+
+    #[test]
+    fn apply_and_undo_changes() {
+        let mut token = Token::new("gimli");
+        let changes = [
+            change("gimli", "hp", None, Value::Integer(20)),
+            change("gimli", "hp", Some(Value::Integer(20)), Value::Integer(12)),
+            change("gimli", "speed", None, Value::Decimal(7.5)),
+        ];
+
+        assert_eq!(token.apply_change(&changes[0]), Ok(None));
+        assert_eq!(token.apply_change(&changes[1]), Ok(Some(20.0)));
+        assert_eq!(token.apply_change(&changes[2]), Ok(None));
+        assert_eq!(token.attributes()["hp"], 12.0);
+        assert_eq!(token.attributes()["speed"], 7.5);
+
+        for change in changes.iter().rev() {
+            token.undo_change(change).unwrap();
+        }
+        assert!(token.attributes().is_empty());
+    }
+
+    #[test]
+    fn apply_change_rejects_invalid_changes() {
+        let mut token = Token::new("gimli");
+
+        assert_eq!(
+            token.apply_change(&change("troll", "hp", None, Value::Integer(4))),
+            Err(ChangeError::WrongToken {
+                token: "gimli".to_owned(),
+                changed: "troll".to_owned(),
+            })
+        );
+        assert_eq!(
+            token.undo_change(&change("troll", "hp", None, Value::Integer(4))),
+            Err(ChangeError::WrongToken {
+                token: "gimli".to_owned(),
+                changed: "troll".to_owned(),
+            })
+        );
+        assert!(matches!(
+            token.apply_change(&change("gimli", "name", None, Value::String("Gimli".to_owned()))),
+            Err(ChangeError::InvalidValue(_))
+        ));
+        assert!(token.attributes().is_empty());
+    }
+}
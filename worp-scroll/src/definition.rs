@@ -1,46 +1,95 @@
-use super::{error::DocumentError, variable::VariableList, SpanList};
-use crate::{next_pair, parser::Rule, Symbol};
-use pest::iterators::Pairs;
+use super::{error::DocumentError, variable::VariableList, Documentation, Metadata, SpanList};
+use crate::{next_pair, parser::Rule, Location, Symbol};
+use pest::iterators::{Pair, Pairs};
 use std::{
     convert::{TryFrom, TryInto as _},
+    fmt,
     ops::{Deref, DerefMut},
 };
 
 #[derive(Debug)]
 pub struct Definition {
     pub name: Option<Symbol>,
+    pub documentation: Documentation,
+    pub metadata: Metadata,
     pub variables: VariableList,
     pub body: SpanList,
+    pub location: Location,
 }
 
-impl TryFrom<Pairs<'_, Rule>> for Definition {
+impl TryFrom<Pair<'_, Rule>> for Definition {
     type Error = DocumentError;
 
-    fn try_from(mut macro_definition_pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
+    fn try_from(macro_definition_pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let location = macro_definition_pair.as_span().into();
+        let mut macro_definition_pairs = macro_definition_pair.into_inner();
         let name = match macro_definition_pairs.peek() {
             Some(pair) if pair.as_rule() == Rule::sub_macro_header => {
                 let sub_macro_header_pair = next_pair!(macro_definition_pairs => Rule::sub_macro_header);
-                let sub_macro_name = next_pair!(sub_macro_header_pair.into_inner() => Rule::macro_name)
-                    .into_inner()
-                    .try_into()?;
+                let sub_macro_name = next_pair!(sub_macro_header_pair.into_inner() => Rule::macro_name).try_into()?;
 
                 Some(sub_macro_name)
             }
             _ => None,
         };
 
+        let docs_pair = next_pair!(macro_definition_pairs => Rule::docs);
+        let documentation = docs_pair.clone().try_into()?;
+        let metadata = docs_pair.try_into()?;
+
         let variables_header_pair = next_pair!(macro_definition_pairs => Rule::variable_header);
         let variables = variables_header_pair.into_inner().try_into()?;
 
         let body_pair = next_pair!(macro_definition_pairs => Rule::macro_body);
         let body = body_pair.into_inner().try_into()?;
 
-        let definition = Definition { name, variables, body };
+        let definition = Definition {
+            name,
+            documentation,
+            metadata,
+            variables,
+            body,
+            location,
+        };
 
         Ok(definition)
     }
 }
 
+/// Formats the definition as canonical scroll source.
+///
+/// Sections are separated by a single blank line and the `:=` of every variable declaration is aligned.
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = &self.name {
+            writeln!(f, "== {} ==", name)?;
+        }
+
+        if !self.documentation.is_empty() || !self.documentation.tags.is_empty() {
+            write!(f, "{}", self.documentation)?;
+            writeln!(f)?;
+        }
+
+        if !self.variables.is_empty() {
+            let width = self
+                .variables
+                .iter()
+                .map(|variable| variable.name.to_string().chars().count())
+                .max()
+                .unwrap_or_default();
+
+            for variable in self.variables.iter() {
+                let name = variable.name.to_string();
+                writeln!(f, "{:width$} := {}", name, variable.kind, width = width)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        write!(f, "{}", self.body)
+    }
+}
+
 #[derive(Debug)]
 pub struct DefinitionList(Vec<Definition>);
 
@@ -66,7 +115,7 @@ impl TryFrom<Pairs<'_, Rule>> for DefinitionList {
 
         for next_pair in pairs {
             if let Rule::sub_macro = next_pair.as_rule() {
-                let sub_macro = next_pair.into_inner().try_into()?;
+                let sub_macro = next_pair.try_into()?;
                 macro_definitions.push(sub_macro);
             } else {
                 unreachable!()
@@ -2,14 +2,21 @@ use super::{error::DocumentError, Definition, DefinitionList};
 use crate::{
     next_pair,
     parser::{DocumentParser, Rule},
+    types::{self, Schema},
+    validation,
+};
+use pest::{iterators::Pair, Parser as _};
+use std::{
+    convert::{TryFrom, TryInto as _},
+    fmt,
 };
-use pest::{iterators::Pairs, Parser as _};
-use std::convert::{TryFrom, TryInto as _};
 
 #[derive(Debug)]
 pub struct Document {
     pub main_macro: Definition,
     pub sub_macros: DefinitionList,
+    /// The source the document was parsed from, exactly as it was written.
+    pub source: String,
 }
 
 impl Document {
@@ -25,22 +32,112 @@ impl Document {
         let mut parsed_input = DocumentParser::parse(Rule::document, input)?;
         let document_pair = next_pair!(parsed_input => Rule::document);
 
-        document_pair.into_inner().try_into()
+        document_pair.try_into()
+    }
+
+    /// Checks the document for mistakes that parse correctly, but would fail or misbehave when executed,
+    /// such as links to sub-macros that don't exist or variables that are used without being declared.
+    /// Every mistake found is reported, in the order they appear in the document.
+    ///
+    /// ```
+    /// # use worp_scroll::{Document, error::DocumentError};
+    /// let document = Document::try_from_str("[Roll Damage](#roll_damge)\n\n== #roll_damage ==\n{% 1d8 %}")?;
+    /// let errors = document.validate().unwrap_err();
+    ///
+    /// assert!(matches!(&errors[..], [DocumentError::UnknownLinkTarget { name, .. }] if name == "roll_damge"));
+    /// # Ok::<(), DocumentError>(())
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<DocumentError>> {
+        let errors = validation::validate(self);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks that every expression in the document is used with values of the right type, such as rolling
+    /// `6d"abc"` or adding a string to a number, given the declared types of the pre-defined tables.
+    /// Identifiers that aren't declared by the schema are reported, unless they name a die such as `3dBoost`.
+    ///
+    /// ```
+    /// # use worp_scroll::{Document, Schema, Type, error::{DocumentError, TypeError}};
+    /// let document = Document::try_from_str("{% 6d\"abc\" %}")?;
+    /// let errors = document.type_check(&Schema::new()).unwrap_err();
+    ///
+    /// assert!(matches!(
+    ///     &errors[..],
+    ///     [DocumentError::TypeError { error: TypeError::TypeMismatch { found: Type::String, .. }, .. }]
+    /// ));
+    /// # Ok::<(), DocumentError>(())
+    /// ```
+    pub fn type_check(&self, schema: &Schema) -> Result<(), Vec<DocumentError>> {
+        let errors = types::type_check(self, schema);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Finds a sub-macro of this document by its name, excluding the leading `#`.
+    pub fn sub_macro(&self, name: &str) -> Option<&Definition> {
+        self.sub_macros
+            .iter()
+            .find(|sub_macro| matches!(&sub_macro.name, Some(symbol) if symbol.name == name))
     }
 }
 
-impl TryFrom<Pairs<'_, Rule>> for Document {
+impl TryFrom<Pair<'_, Rule>> for Document {
     type Error = DocumentError;
 
-    fn try_from(mut document_pairs: Pairs<Rule>) -> Result<Self, Self::Error> {
+    fn try_from(document_pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let source = document_pair.as_str().to_owned();
+        let mut document_pairs = document_pair.into_inner();
         let main_macro_pair = next_pair!(document_pairs => Rule::main_macro);
-        let main_macro = main_macro_pair.into_inner().try_into()?;
+        let main_macro = main_macro_pair.try_into()?;
 
         let sub_macros_list_pair = next_pair!(document_pairs => Rule::sub_macro_list);
         let sub_macros = sub_macros_list_pair.into_inner().try_into()?;
 
-        let document = Document { main_macro, sub_macros };
+        let document = Document {
+            main_macro,
+            sub_macros,
+            source,
+        };
 
         Ok(document)
     }
 }
+
+/// Formats the document as canonical scroll source, which parses back into an equivalent document.
+///
+/// ```
+/// # use worp_scroll::{Document, error::DocumentError};
+/// let document = Document::try_from_str("$strength_mod:={%global.ability_mods[self.strength]%}\n$x:={%1d20+$strength_mod%}\n\n*$x*\n==   #roll_damage==\n{%(1d8)+2%}")?;
+///
+/// assert_eq!(
+///     document.to_string(),
+///     "$strength_mod := {% global.ability_mods[self.strength] %}\n\
+///      $x            := {% 1d20 + $strength_mod %}\n\
+///      \n\
+///      *$x*\n\
+///      \n\
+///      == #roll_damage ==\n\
+///      {% 1d8 + 2 %}"
+/// );
+/// # Ok::<(), DocumentError>(())
+/// ```
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.main_macro)?;
+
+        for sub_macro in self.sub_macros.iter() {
+            write!(f, "\n\n{}", sub_macro)?;
+        }
+
+        Ok(())
+    }
+}
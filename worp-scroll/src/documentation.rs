@@ -0,0 +1,203 @@
+use super::error::DocumentError;
+use crate::{next_pair, parser::Rule, Location};
+use pest::iterators::Pair;
+use std::{collections::BTreeMap, convert::TryFrom, fmt};
+
+/// The `>` prefixed lines documenting a macro, with the `>` and any leading whitespace removed.
+/// Lines containing a tag, such as `> @tag combat`, are kept in `tags` and parsed into the macro's `Metadata`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Documentation {
+    pub lines: Vec<String>,
+    /// The tag lines, such as `@tag combat`, each along with the number of `lines` written before it,
+    /// so the documentation can be formatted in its original order.
+    pub tags: Vec<(usize, String)>,
+    pub location: Location,
+}
+
+impl Documentation {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The documentation as a single block of text, with each line separated by a newline.
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+}
+
+/// Formats the documentation's lines and tags in the order they were written.
+impl fmt::Display for Documentation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for index in 0..=self.lines.len() {
+            for (_, tag) in self.tags.iter().filter(|(position, _)| *position == index) {
+                writeln!(f, "> {}", tag)?;
+            }
+
+            match self.lines.get(index) {
+                Some(line) if line.is_empty() => writeln!(f, ">")?,
+                Some(line) => writeln!(f, "> {}", line)?,
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<Pair<'_, Rule>> for Documentation {
+    type Error = DocumentError;
+
+    fn try_from(docs_pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let location = docs_pair.as_span().into();
+        let mut lines = Vec::new();
+        let mut tags = Vec::new();
+
+        for docs_line_pair in docs_pair.into_inner() {
+            match docs_line_pair.as_rule() {
+                Rule::docs_line => lines.push(docs_line_pair.as_str().trim_end().to_owned()),
+                Rule::docs_tag => {
+                    let mut docs_tag_pairs = docs_line_pair.into_inner();
+                    let name = next_pair!(docs_tag_pairs => Rule::identifier).as_str();
+                    let value = next_pair!(docs_tag_pairs => Rule::docs_tag_value).as_str().trim();
+                    let tag = if value.is_empty() {
+                        format!("@{}", name)
+                    } else {
+                        format!("@{} {}", name, value)
+                    };
+
+                    tags.push((lines.len(), tag));
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(Documentation { lines, tags, location })
+    }
+}
+
+/// The tags found in a macro's documentation, used to organize and search for macros.
+///
+/// The well-known tags are:
+///
+/// * `@param $name description` documents a variable used by the macro.
+/// * `@tag combat, melee` adds one or more tags, separated by commas or whitespace.
+/// * `@author name` names an author of the macro.
+/// * `@system dnd5e` names a game system the macro is written for.
+///
+/// Any other tags are kept, by name, in `other`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub params: Vec<Parameter>,
+    pub tags: Vec<String>,
+    pub authors: Vec<String>,
+    pub systems: Vec<String>,
+    pub other: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub description: String,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+            && self.tags.is_empty()
+            && self.authors.is_empty()
+            && self.systems.is_empty()
+            && self.other.is_empty()
+    }
+
+    /// Checks if the macro has been tagged with the given tag, ignoring case.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|existing| existing.eq_ignore_ascii_case(tag))
+    }
+
+    /// Checks if the macro is written for the given game system, ignoring case.
+    pub fn has_system(&self, system: &str) -> bool {
+        self.systems
+            .iter()
+            .any(|existing| existing.eq_ignore_ascii_case(system))
+    }
+}
+
+/// Formats the metadata as documentation tags, with all of the `@tag` tags combined into a single line.
+/// Documents are formatted with the tags of their `Documentation` instead, which keeps their original order.
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.tags.is_empty() {
+            writeln!(f, "> @tag {}", self.tags.join(", "))?;
+        }
+
+        for system in &self.systems {
+            writeln!(f, "> @system {}", system)?;
+        }
+
+        for author in &self.authors {
+            writeln!(f, "> @author {}", author)?;
+        }
+
+        for param in &self.params {
+            write!(f, "> @param ${}", param.name)?;
+
+            if param.description.is_empty() {
+                writeln!(f)?;
+            } else {
+                writeln!(f, " {}", param.description)?;
+            }
+        }
+
+        for (name, values) in &self.other {
+            for value in values {
+                if value.is_empty() {
+                    writeln!(f, "> @{}", name)?;
+                } else {
+                    writeln!(f, "> @{} {}", name, value)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<Pair<'_, Rule>> for Metadata {
+    type Error = DocumentError;
+
+    fn try_from(docs_pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let mut metadata = Metadata::default();
+
+        for docs_tag_pair in docs_pair.into_inner().filter(|pair| pair.as_rule() == Rule::docs_tag) {
+            let mut docs_tag_pairs = docs_tag_pair.into_inner();
+            let name = next_pair!(docs_tag_pairs => Rule::identifier).as_str();
+            let value = next_pair!(docs_tag_pairs => Rule::docs_tag_value).as_str().trim();
+
+            match name {
+                "param" => {
+                    let mut parts = value.splitn(2, char::is_whitespace);
+                    let name = parts.next().unwrap_or_default().trim_start_matches('$').to_owned();
+                    let description = parts.next().unwrap_or_default().trim().to_owned();
+
+                    metadata.params.push(Parameter { name, description });
+                }
+                "tag" => metadata.tags.extend(
+                    value
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|tag| !tag.is_empty())
+                        .map(str::to_owned),
+                ),
+                "author" => metadata.authors.push(value.to_owned()),
+                "system" => metadata.systems.push(value.to_owned()),
+                _ => metadata
+                    .other
+                    .entry(name.to_owned())
+                    .or_default()
+                    .push(value.to_owned()),
+            }
+        }
+
+        Ok(metadata)
+    }
+}
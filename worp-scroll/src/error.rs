@@ -1,7 +1,165 @@
-use crate::parser;
+use crate::{
+    expression::RangeOperator,
+    parser,
+    runtime::{InputRequest, Value},
+    types::Type,
+    Location,
+};
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
 pub enum DocumentError {
     #[error(transparent)]
     ParseError(#[from] pest::error::Error<parser::Rule>),
+    #[error("Link target `#{name}` is not a sub-macro of this document ({location}).")]
+    UnknownLinkTarget { name: String, location: Location },
+    #[error("Variable `${name}` is not defined ({location}).")]
+    UndefinedVariable { name: String, location: Location },
+    #[error("Sub-macro `#{name}` is already defined at {previous} ({location}).")]
+    DuplicateSubMacro {
+        name: String,
+        location: Location,
+        previous: Location,
+    },
+    #[error("Variable `${name}` is declared by sub-macro `#{sub_macro}` and is not in scope ({location}).")]
+    OutOfScopeVariable {
+        name: String,
+        sub_macro: String,
+        location: Location,
+    },
+    #[error("{error} ({location})")]
+    TypeError { error: TypeError, location: Location },
+    #[error("Value `{value}` is not an answer accepted by the prompt for `${name}` ({location}).")]
+    InvalidPromptValue {
+        name: String,
+        value: String,
+        location: Location,
+    },
+}
+
+/// A mistake found by checking the types of a document's expressions before it's run.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum TypeError {
+    #[error("Expected a value of type {expected}, but found a value of type {found}.")]
+    TypeMismatch { expected: &'static str, found: Type },
+    #[error("Identifier `{0}` is not declared.")]
+    UndeclaredIdentifier(String),
+    #[error("Key `{0}` is not declared in the table.")]
+    UnknownKey(String),
+    #[error("Function `{0}` is not defined.")]
+    UndefinedFunction(String),
+    #[error("Function `{function}` expects {expected} argument(s), but was given {found}.")]
+    ArgumentCount {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("List values must all be the same type, but found values of type {first} and {second}.")]
+    MixedList { first: Type, second: Type },
+    #[error("Cannot assign to `{0}`, only to the attributes of tokens such as `self.hp`.")]
+    InvalidAssignment(String),
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum EvaluationError {
+    #[error("Variable `${0}` is not defined.")]
+    UndefinedVariable(String),
+    #[error("Identifier `{0}` is not defined.")]
+    UndefinedIdentifier(String),
+    #[error("Function `{0}` is not defined.")]
+    UndefinedFunction(String),
+    #[error("Function `{function}` expects {expected} argument(s), but was given {found}.")]
+    ArgumentCount {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Expected a value of type {expected}, but found a value of type {found}.")]
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("Key `{0}` was not found in the table.")]
+    KeyNotFound(String),
+    #[error("Index {0} is out of bounds.")]
+    IndexOutOfBounds(i64),
+    #[error("Cannot roll {0} dice.")]
+    InvalidDiceCount(i64),
+    #[error("Cannot roll dice with {0} sides.")]
+    InvalidDiceSides(i64),
+    #[error("Die `d{0}` is not defined.")]
+    UndefinedDie(String),
+    #[error("Cannot roll more than {0} dice in a single roll.")]
+    DiceLimitExceeded(u64),
+    #[error("Cannot roll more than {0} dice in total.")]
+    TotalDiceLimitExceeded(u64),
+    #[error("Evaluation took more than {0} steps.")]
+    StepLimitExceeded(u64),
+    #[error("Cannot build a value larger than {0} bytes.")]
+    ValueSizeLimitExceeded(usize),
+    #[error("Cannot keep or drop {0} dice.")]
+    InvalidModifierCount(i64),
+    #[error("Dice modifier `{0}` matches every face of the die, so it would never stop rolling.")]
+    EndlessModifier(String),
+    #[error("Range `{start}{operator}{end}` is inverted, its start must be less than its end.")]
+    InvertedRange {
+        operator: RangeOperator,
+        start: i64,
+        end: i64,
+    },
+    #[error("Range `{start}{operator}{end}` is empty, as it excludes every value between its bounds.")]
+    EmptyRange {
+        operator: RangeOperator,
+        start: i64,
+        end: i64,
+    },
+    #[error("Cannot assign to `{0}`, only to the attributes of tokens such as `self.hp`.")]
+    InvalidAssignment(String),
+    #[error("Expected a list with at least one value.")]
+    EmptyList,
+    #[error("Division by zero.")]
+    DivisionByZero,
+    #[error("Arithmetic overflow.")]
+    Overflow,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ExecutionError {
+    #[error("{error} ({location})")]
+    EvaluationError { error: EvaluationError, location: Location },
+    #[error("Macro `#{0}` is not defined.")]
+    UndefinedMacro(String),
+    #[error("Macro calls itself recursively: {}.", format_call_stack(.0))]
+    RecursiveMacroCall(Vec<String>),
+    #[error("The document of the continuation is invalid: {0}")]
+    InvalidContinuation(DocumentError),
+    #[error("Menu option {0} does not exist.")]
+    UnknownMenuOption(usize),
+    #[error("Macros cannot be embedded more than {0} deep.")]
+    CallDepthExceeded(usize),
+    #[error("Output cannot be longer than {0} bytes.")]
+    OutputLimitExceeded(usize),
+    /// Not a failure, the macro needs the player to answer its prompts before it can be run again.
+    #[error("Macro needs input for {}.", format_fields(.0))]
+    InputRequired(InputRequest),
+    #[error("`{value}` is not an answer accepted by the prompt for `${variable}`.")]
+    InvalidInput { variable: String, value: Value },
+    #[error("Variable `${0}` is prompted for by more than one macro, each asking something different.")]
+    ConflictingPrompts(String),
+}
+
+fn format_fields(request: &InputRequest) -> String {
+    request
+        .fields
+        .iter()
+        .map(|field| format!("${}", field.variable))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_call_stack(call_stack: &[String]) -> String {
+    call_stack
+        .iter()
+        .map(|name| format!("#{}", name))
+        .collect::<Vec<_>>()
+        .join(" -> ")
 }
@@ -1,19 +1,1126 @@
 use super::error::DocumentError;
 use crate::{next_pair, parser::Rule};
-use pest::iterators::Pairs;
-use std::convert::TryFrom;
+use pest::{
+    error::{Error, ErrorVariant},
+    iterators::{Pair, Pairs},
+};
+use std::{convert::TryFrom, fmt};
 
-// TODO: Figure out how to handle this.  Should this be parsed to a Dice expression here?
-#[derive(Debug)]
-pub struct Expression(String);
+/// A DICE expression, as parsed from the body of an `{% ... %}` placeholder.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(Literal),
+    List(Vec<Expression>),
+    Variable(String),
+    Identifier(String),
+    Field {
+        target: Box<Expression>,
+        field: String,
+    },
+    Index {
+        target: Box<Expression>,
+        index: Box<Expression>,
+    },
+    Call {
+        function: String,
+        arguments: Vec<Expression>,
+    },
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expression>,
+    },
+    Binary {
+        operator: BinaryOperator,
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    Range {
+        operator: RangeOperator,
+        start: Box<Expression>,
+        end: Box<Expression>,
+    },
+    Roll {
+        count: Box<Expression>,
+        sides: Box<Expression>,
+        modifiers: Vec<RollModifier>,
+    },
+    /// An assignment to a token's attribute, such as `self.hp -= 8`.
+    Assign {
+        operator: AssignmentOperator,
+        target: Box<Expression>,
+        value: Box<Expression>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Integer(i64),
+    Decimal(f64),
+    Boolean(bool),
+    String(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+/// The operators that assign to a token's attribute, with all but `=` combining the attribute with the new value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignmentOperator {
+    Assign,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl AssignmentOperator {
+    /// The operator used to combine the attribute with the assigned value, if any.
+    pub fn binary_operator(self) -> Option<BinaryOperator> {
+        match self {
+            AssignmentOperator::Assign => None,
+            AssignmentOperator::Add => Some(BinaryOperator::Add),
+            AssignmentOperator::Subtract => Some(BinaryOperator::Subtract),
+            AssignmentOperator::Multiply => Some(BinaryOperator::Multiply),
+            AssignmentOperator::Divide => Some(BinaryOperator::Divide),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+/// The four range operators, named after which of their bounds are included in the range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeOperator {
+    /// `n..m`
+    Exclusive,
+    /// `n=..m`
+    InclusiveStart,
+    /// `n..=m`
+    InclusiveEnd,
+    /// `n=..=m`
+    Inclusive,
+}
+
+/// A modifier applied to the dice of a roll, such as the `kh3` of `4d6kh3`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RollModifier {
+    /// `khN` or `klN`, where `N` defaults to one.
+    Keep {
+        selection: Selection,
+        count: Option<Expression>,
+    },
+    /// `dhN` or `dlN`, where `N` defaults to one.
+    Drop {
+        selection: Selection,
+        count: Option<Expression>,
+    },
+    /// `adv`
+    Advantage,
+    /// `dis`
+    Disadvantage,
+    /// `rC` rerolls dice until they no longer meet the condition, while `roC` rerolls them at most once.
+    Reroll { once: bool, condition: RollCondition },
+    /// `!C`, `!!C` or `!pC`, where the condition defaults to rolling the highest face.
+    Explode {
+        kind: ExplodeKind,
+        condition: Option<RollCondition>,
+    },
+    /// `csC`, which makes the roll a dice pool counting the dice that meet the condition as successes.
+    /// The condition defaults to rolling the highest face.
+    CountSuccesses { condition: Option<RollCondition> },
+    /// `cfC`, which counts the dice that meet the condition as failures, such as botches.
+    /// The condition defaults to rolling the lowest face.
+    CountFailures { condition: Option<RollCondition> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    Highest,
+    Lowest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplodeKind {
+    /// Each die that explodes adds another die to the roll.
+    Exploding,
+    /// Each die that explodes is rolled again and added to its own value.
+    Compounding,
+    /// Like exploding, but each extra die has one subtracted from it.
+    Penetrating,
+}
+
+/// A comparison against the value of a single die, such as the `<2` of `r<2`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollCondition {
+    pub operator: ConditionOperator,
+    pub value: Expression,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionOperator {
+    Equal,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+impl Expression {
+    /// Calls the visitor with this expression and then every expression nested inside of it, in source order.
+    pub fn visit<'a>(&'a self, visitor: &mut impl FnMut(&'a Expression)) {
+        visitor(self);
+
+        match self {
+            Expression::Literal(_) | Expression::Variable(_) | Expression::Identifier(_) => {}
+            Expression::List(items) | Expression::Call { arguments: items, .. } => {
+                for item in items {
+                    item.visit(visitor);
+                }
+            }
+            Expression::Field { target, .. } => target.visit(visitor),
+            Expression::Assign { target, value, .. } => {
+                target.visit(visitor);
+                value.visit(visitor);
+            }
+            Expression::Index { target, index } => {
+                target.visit(visitor);
+                index.visit(visitor);
+            }
+            Expression::Unary { operand, .. } => operand.visit(visitor),
+            Expression::Binary { lhs, rhs, .. } => {
+                lhs.visit(visitor);
+                rhs.visit(visitor);
+            }
+            Expression::Range { start, end, .. } => {
+                start.visit(visitor);
+                end.visit(visitor);
+            }
+            Expression::Roll {
+                count,
+                sides,
+                modifiers,
+            } => {
+                count.visit(visitor);
+                sides.visit(visitor);
+
+                for modifier in modifiers {
+                    match modifier {
+                        RollModifier::Keep { count, .. } | RollModifier::Drop { count, .. } => {
+                            if let Some(count) = count {
+                                count.visit(visitor);
+                            }
+                        }
+                        RollModifier::Reroll { condition, .. }
+                        | RollModifier::Explode {
+                            condition: Some(condition),
+                            ..
+                        }
+                        | RollModifier::CountSuccesses {
+                            condition: Some(condition),
+                        }
+                        | RollModifier::CountFailures {
+                            condition: Some(condition),
+                        } => condition.value.visit(visitor),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
 
 impl TryFrom<Pairs<'_, Rule>> for Expression {
     type Error = DocumentError;
 
     fn try_from(mut expression_pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
         let expression_pair = next_pair!(expression_pairs => Rule::expression);
-        let expression = expression_pair.into_inner().as_str().to_owned();
+        let expression_body_pair = next_pair!(expression_pair.into_inner() => Rule::expression_body);
+        let dice_expression_pair =
+            next_pair!(expression_body_pair.into_inner() => Rule::dice_expression | Rule::assignment_expression);
+
+        Expression::try_from(dice_expression_pair)
+    }
+}
+
+impl TryFrom<Pair<'_, Rule>> for Expression {
+    type Error = DocumentError;
+
+    fn try_from(pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let expression = match pair.as_rule() {
+            Rule::dice_expression | Rule::primary_expression => {
+                let inner_pair = pair.into_inner().next().unwrap_or_else(|| unreachable!());
+                Expression::try_from(inner_pair)?
+            }
+            Rule::logical_or_expression
+            | Rule::logical_and_expression
+            | Rule::comparison_expression
+            | Rule::additive_expression
+            | Rule::multiplicative_expression => {
+                let mut pairs = pair.into_inner();
+                let mut lhs = Expression::try_from(pairs.next().unwrap_or_else(|| unreachable!()))?;
+
+                while let Some(operator_pair) = pairs.next() {
+                    let operator = BinaryOperator::from(operator_pair);
+                    let rhs = Expression::try_from(pairs.next().unwrap_or_else(|| unreachable!()))?;
+
+                    lhs = Expression::Binary {
+                        operator,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    };
+                }
+
+                lhs
+            }
+            Rule::assignment_expression => {
+                let mut pairs = pair.into_inner();
+                let target = Expression::try_from(next_pair!(pairs => Rule::postfix_expression))?;
+                let operator = AssignmentOperator::from(next_pair!(pairs => Rule::assignment_operator));
+                let value = Expression::try_from(next_pair!(pairs => Rule::dice_expression))?;
+
+                Expression::Assign {
+                    operator,
+                    target: Box::new(target),
+                    value: Box::new(value),
+                }
+            }
+            Rule::range_expression => {
+                let mut pairs = pair.into_inner();
+                let start = Expression::try_from(next_pair!(pairs => Rule::additive_expression))?;
+
+                match pairs.next() {
+                    Some(operator_pair) => {
+                        let operator = RangeOperator::from(operator_pair);
+                        let end = Expression::try_from(next_pair!(pairs => Rule::additive_expression))?;
+
+                        Expression::Range {
+                            operator,
+                            start: Box::new(start),
+                            end: Box::new(end),
+                        }
+                    }
+                    None => start,
+                }
+            }
+            Rule::unary_expression => {
+                let mut pairs = pair.into_inner().collect::<Vec<_>>();
+                let operand_pair = pairs.pop().filter(|pair| pair.as_rule() == Rule::roll_expression);
+                let mut operand = Expression::try_from(operand_pair.unwrap_or_else(|| unreachable!()))?;
+
+                for operator_pair in pairs.into_iter().rev() {
+                    operand = Expression::Unary {
+                        operator: UnaryOperator::from(operator_pair),
+                        operand: Box::new(operand),
+                    };
+                }
+
+                operand
+            }
+            Rule::roll_expression => {
+                let mut pairs = pair.into_inner().peekable();
+                let mut count = Expression::try_from(next_pair!(pairs => Rule::postfix_expression))?;
+
+                while let Some(sides_pair) = pairs.next() {
+                    let sides = match sides_pair.as_rule() {
+                        Rule::percentile_die => Expression::Literal(Literal::Integer(100)),
+                        _ => Expression::try_from(sides_pair)?,
+                    };
+                    let mut modifiers = Vec::new();
+
+                    while let Some(modifier_pair) = pairs.next_if(|pair| pair.as_rule() == Rule::roll_modifier) {
+                        modifiers.push(RollModifier::try_from(modifier_pair)?);
+                    }
+
+                    count = Expression::Roll {
+                        count: Box::new(count),
+                        sides: Box::new(sides),
+                        modifiers,
+                    };
+                }
+
+                count
+            }
+            Rule::postfix_expression => {
+                let mut pairs = pair.into_inner();
+                let mut target = Expression::try_from(next_pair!(pairs => Rule::primary_expression))?;
+
+                for accessor_pair in pairs {
+                    target = match accessor_pair.as_rule() {
+                        Rule::field_access => Expression::Field {
+                            target: Box::new(target),
+                            field: next_pair!(accessor_pair.into_inner() => Rule::identifier)
+                                .as_str()
+                                .to_owned(),
+                        },
+                        Rule::index_access => Expression::Index {
+                            target: Box::new(target),
+                            index: Box::new(Expression::try_from(
+                                next_pair!(accessor_pair.into_inner() => Rule::dice_expression),
+                            )?),
+                        },
+                        _ => unreachable!(),
+                    };
+                }
+
+                target
+            }
+            Rule::integer_literal => {
+                let integer = pair
+                    .as_str()
+                    .parse()
+                    .map_err(|_| invalid_literal(&pair, "Integer literal is out of range."))?;
+
+                Expression::Literal(Literal::Integer(integer))
+            }
+            Rule::decimal_literal => {
+                let decimal = pair
+                    .as_str()
+                    .parse()
+                    .map_err(|_| invalid_literal(&pair, "Decimal literal is invalid."))?;
+
+                Expression::Literal(Literal::Decimal(decimal))
+            }
+            Rule::boolean_literal => Expression::Literal(Literal::Boolean(pair.as_str() == "true")),
+            Rule::string_literal => {
+                let string_content = next_pair!(pair.into_inner() => Rule::string_content).as_str();
+                Expression::Literal(Literal::String(unescape(string_content)))
+            }
+            Rule::list_literal => {
+                let items = pair.into_inner().map(Expression::try_from).collect::<Result<_, _>>()?;
+                Expression::List(items)
+            }
+            Rule::variable_name => {
+                let mut variable_name_pairs = pair.into_inner();
+                next_pair!(variable_name_pairs => Rule::variable_name_indicator);
+                let name = next_pair!(variable_name_pairs => Rule::identifier).as_str().to_owned();
+
+                Expression::Variable(name)
+            }
+            Rule::function_call => {
+                let mut function_call_pairs = pair.into_inner();
+                let function = next_pair!(function_call_pairs => Rule::identifier).as_str().to_owned();
+                let arguments = function_call_pairs
+                    .map(Expression::try_from)
+                    .collect::<Result<_, _>>()?;
+
+                Expression::Call { function, arguments }
+            }
+            Rule::identifier => Expression::Identifier(pair.as_str().to_owned()),
+            _ => unreachable!(),
+        };
+
+        Ok(expression)
+    }
+}
+
+impl TryFrom<Pair<'_, Rule>> for RollModifier {
+    type Error = DocumentError;
+
+    fn try_from(pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let modifier_pair = next_pair!(pair.into_inner() => _);
+        let rule = modifier_pair.as_rule();
+        let mut pairs = modifier_pair.into_inner();
+
+        let modifier = match rule {
+            Rule::keep_highest_modifier | Rule::keep_lowest_modifier => RollModifier::Keep {
+                selection: Selection::from(rule),
+                count: pairs.next().map(Expression::try_from).transpose()?,
+            },
+            Rule::drop_highest_modifier | Rule::drop_lowest_modifier => RollModifier::Drop {
+                selection: Selection::from(rule),
+                count: pairs.next().map(Expression::try_from).transpose()?,
+            },
+            Rule::advantage_modifier => RollModifier::Advantage,
+            Rule::disadvantage_modifier => RollModifier::Disadvantage,
+            Rule::reroll_modifier | Rule::reroll_once_modifier => RollModifier::Reroll {
+                once: rule == Rule::reroll_once_modifier,
+                condition: RollCondition::try_from(next_pair!(pairs => Rule::roll_condition))?,
+            },
+            Rule::exploding_modifier | Rule::compounding_modifier | Rule::penetrating_modifier => {
+                RollModifier::Explode {
+                    kind: ExplodeKind::from(rule),
+                    condition: pairs.next().map(RollCondition::try_from).transpose()?,
+                }
+            }
+            Rule::count_successes_modifier => RollModifier::CountSuccesses {
+                condition: pairs.next().map(RollCondition::try_from).transpose()?,
+            },
+            Rule::count_failures_modifier => RollModifier::CountFailures {
+                condition: pairs.next().map(RollCondition::try_from).transpose()?,
+            },
+            _ => unreachable!(),
+        };
+
+        Ok(modifier)
+    }
+}
+
+impl TryFrom<Pair<'_, Rule>> for RollCondition {
+    type Error = DocumentError;
+
+    fn try_from(pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let mut pairs = pair.into_inner();
+        let operator = match pairs.peek().map(|pair| pair.as_rule()) {
+            Some(Rule::condition_operator) => ConditionOperator::from(next_pair!(pairs => Rule::condition_operator)),
+            _ => ConditionOperator::Equal,
+        };
+        let value = Expression::try_from(next_pair!(pairs => _))?;
+
+        Ok(RollCondition { operator, value })
+    }
+}
+
+impl From<Rule> for Selection {
+    fn from(rule: Rule) -> Self {
+        match rule {
+            Rule::keep_highest_modifier | Rule::drop_highest_modifier => Selection::Highest,
+            Rule::keep_lowest_modifier | Rule::drop_lowest_modifier => Selection::Lowest,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<Rule> for ExplodeKind {
+    fn from(rule: Rule) -> Self {
+        match rule {
+            Rule::exploding_modifier => ExplodeKind::Exploding,
+            Rule::compounding_modifier => ExplodeKind::Compounding,
+            Rule::penetrating_modifier => ExplodeKind::Penetrating,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<Pair<'_, Rule>> for ConditionOperator {
+    fn from(pair: Pair<'_, Rule>) -> Self {
+        match pair.as_str() {
+            "=" => ConditionOperator::Equal,
+            "<" => ConditionOperator::LessThan,
+            "<=" => ConditionOperator::LessThanOrEqual,
+            ">" => ConditionOperator::GreaterThan,
+            ">=" => ConditionOperator::GreaterThanOrEqual,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<Pair<'_, Rule>> for UnaryOperator {
+    fn from(pair: Pair<'_, Rule>) -> Self {
+        match pair.as_str() {
+            "-" => UnaryOperator::Negate,
+            "!" => UnaryOperator::Not,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<Pair<'_, Rule>> for AssignmentOperator {
+    fn from(pair: Pair<'_, Rule>) -> Self {
+        match pair.as_str() {
+            "=" => AssignmentOperator::Assign,
+            "+=" => AssignmentOperator::Add,
+            "-=" => AssignmentOperator::Subtract,
+            "*=" => AssignmentOperator::Multiply,
+            "/=" => AssignmentOperator::Divide,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<Pair<'_, Rule>> for BinaryOperator {
+    fn from(pair: Pair<'_, Rule>) -> Self {
+        match pair.as_str() {
+            "||" => BinaryOperator::Or,
+            "&&" => BinaryOperator::And,
+            "==" => BinaryOperator::Equal,
+            "!=" => BinaryOperator::NotEqual,
+            "<" => BinaryOperator::LessThan,
+            "<=" => BinaryOperator::LessThanOrEqual,
+            ">" => BinaryOperator::GreaterThan,
+            ">=" => BinaryOperator::GreaterThanOrEqual,
+            "+" => BinaryOperator::Add,
+            "-" => BinaryOperator::Subtract,
+            "*" => BinaryOperator::Multiply,
+            "/" => BinaryOperator::Divide,
+            "%" => BinaryOperator::Remainder,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<Pair<'_, Rule>> for RangeOperator {
+    fn from(pair: Pair<'_, Rule>) -> Self {
+        match pair.as_str() {
+            ".." => RangeOperator::Exclusive,
+            "=.." => RangeOperator::InclusiveStart,
+            "..=" => RangeOperator::InclusiveEnd,
+            "=..=" => RangeOperator::Inclusive,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Expression {
+    /// Whether the expression is written as a name, which would run into any letters that follow it.
+    fn is_name(&self) -> bool {
+        matches!(
+            self,
+            Expression::Variable(_)
+                | Expression::Identifier(_)
+                | Expression::Field { .. }
+                | Expression::Literal(Literal::Boolean(_))
+        )
+    }
+
+    /// The precedence of the expression, matching the order of the rules in the grammar.
+    /// Higher values bind tighter.
+    fn precedence(&self) -> u8 {
+        match self {
+            Expression::Assign { .. } => 0,
+            Expression::Binary { operator, .. } => operator.precedence(),
+            Expression::Range { .. } => 4,
+            Expression::Unary { .. } => 7,
+            Expression::Roll { .. } => 8,
+            Expression::Field { .. } | Expression::Index { .. } => 9,
+            _ => 10,
+        }
+    }
+}
+
+impl BinaryOperator {
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::LessThan
+            | BinaryOperator::LessThanOrEqual
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::GreaterThanOrEqual => 3,
+            BinaryOperator::Add | BinaryOperator::Subtract => 5,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 6,
+        }
+    }
+}
+
+/// Formats the expression as canonical DICE source, adding parentheses only where they're needed.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Literal(literal) => write!(f, "{}", literal),
+            Expression::List(items) => {
+                write!(f, "[")?;
+                write_list(f, items)?;
+                write!(f, "]")
+            }
+            Expression::Variable(name) => write!(f, "${}", name),
+            Expression::Identifier(name) => write!(f, "{}", name),
+            Expression::Field { target, field } => {
+                write_operand(f, target, 9)?;
+                write!(f, ".{}", field)
+            }
+            Expression::Index { target, index } => {
+                write_operand(f, target, 9)?;
+                write!(f, "[{}]", index)
+            }
+            Expression::Call { function, arguments } => {
+                write!(f, "{}(", function)?;
+                write_list(f, arguments)?;
+                write!(f, ")")
+            }
+            Expression::Unary { operator, operand } => {
+                write!(f, "{}", operator)?;
+                write_operand(f, operand, 7)
+            }
+            Expression::Binary { operator, lhs, rhs } => {
+                let precedence = operator.precedence();
+
+                // Comparisons can't be chained, so both of their sides must bind tighter.
+                if precedence == 3 {
+                    write_operand(f, lhs, precedence + 1)?;
+                } else {
+                    write_operand(f, lhs, precedence)?;
+                }
+
+                write!(f, " {} ", operator)?;
+                write_operand(f, rhs, precedence + 1)
+            }
+            Expression::Range { operator, start, end } => {
+                write_operand(f, start, 5)?;
+                write!(f, "{}", operator)?;
+                write_operand(f, end, 5)
+            }
+            Expression::Roll {
+                count,
+                sides,
+                modifiers,
+            } => {
+                // Names followed by the dice operator would read as a longer name, such as `$countd6`.
+                // Roll counts are wrapped as well, as their sides would otherwise read as part of a longer name
+                // or a modifier, such as `1dxd6` or `1d6dl`.
+                if count.is_name() {
+                    write!(f, "({})", count)?;
+                } else {
+                    write_operand(f, count, 9)?;
+                }
+
+                write!(f, "d")?;
+
+                if sides.is_name() && !modifiers.is_empty() {
+                    write!(f, "({})", sides)?;
+                } else {
+                    write_operand(f, sides, 9)?;
+                }
+
+                for modifier in modifiers {
+                    write!(f, "{}", modifier)?;
+                }
+
+                Ok(())
+            }
+            Expression::Assign {
+                operator,
+                target,
+                value,
+            } => write!(f, "{} {} {}", target, operator, value),
+        }
+    }
+}
+
+impl fmt::Display for RollModifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RollModifier::Keep { selection, count } | RollModifier::Drop { selection, count } => {
+                let modifier = match (self, selection) {
+                    (RollModifier::Keep { .. }, Selection::Highest) => "kh",
+                    (RollModifier::Keep { .. }, Selection::Lowest) => "kl",
+                    (_, Selection::Highest) => "dh",
+                    (_, Selection::Lowest) => "dl",
+                };
+                write!(f, "{}", modifier)?;
+
+                match count {
+                    Some(count) => write_modifier_value(f, count),
+                    None => Ok(()),
+                }
+            }
+            RollModifier::Advantage => write!(f, "adv"),
+            RollModifier::Disadvantage => write!(f, "dis"),
+            RollModifier::Reroll { once, condition } => {
+                write!(f, "{}{}", if *once { "ro" } else { "r" }, condition)
+            }
+            RollModifier::Explode { kind, condition } => {
+                match kind {
+                    ExplodeKind::Exploding => write!(f, "!")?,
+                    ExplodeKind::Compounding => write!(f, "!!")?,
+                    ExplodeKind::Penetrating => write!(f, "!p")?,
+                }
+
+                write_condition(f, condition)
+            }
+            RollModifier::CountSuccesses { condition } => {
+                write!(f, "cs")?;
+                write_condition(f, condition)
+            }
+            RollModifier::CountFailures { condition } => {
+                write!(f, "cf")?;
+                write_condition(f, condition)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RollCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operator {
+            ConditionOperator::Equal => {}
+            ConditionOperator::LessThan => write!(f, "<")?,
+            ConditionOperator::LessThanOrEqual => write!(f, "<=")?,
+            ConditionOperator::GreaterThan => write!(f, ">")?,
+            ConditionOperator::GreaterThanOrEqual => write!(f, ">=")?,
+        }
+
+        write_modifier_value(f, &self.value)
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Integer(value) => write!(f, "{}", value),
+            Literal::Decimal(value) => {
+                let decimal = value.to_string();
+
+                if decimal.contains('.') {
+                    write!(f, "{}", decimal)
+                } else {
+                    write!(f, "{}.0", decimal)
+                }
+            }
+            Literal::Boolean(value) => write!(f, "{}", value),
+            Literal::String(value) => {
+                write!(f, "\"")?;
+
+                for c in value.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+
+                write!(f, "\"")
+            }
+        }
+    }
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOperator::Negate => write!(f, "-"),
+            UnaryOperator::Not => write!(f, "!"),
+        }
+    }
+}
+
+impl fmt::Display for AssignmentOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self {
+            AssignmentOperator::Assign => "=",
+            AssignmentOperator::Add => "+=",
+            AssignmentOperator::Subtract => "-=",
+            AssignmentOperator::Multiply => "*=",
+            AssignmentOperator::Divide => "/=",
+        };
+
+        write!(f, "{}", operator)
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self {
+            BinaryOperator::Or => "||",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::LessThanOrEqual => "<=",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::GreaterThanOrEqual => ">=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Remainder => "%",
+        };
+
+        write!(f, "{}", operator)
+    }
+}
+
+impl fmt::Display for RangeOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self {
+            RangeOperator::Exclusive => "..",
+            RangeOperator::InclusiveStart => "=..",
+            RangeOperator::InclusiveEnd => "..=",
+            RangeOperator::Inclusive => "=..=",
+        };
+
+        write!(f, "{}", operator)
+    }
+}
+
+/// Writes an operand, wrapping it in parentheses if it binds looser than the given precedence.
+fn write_operand(f: &mut fmt::Formatter<'_>, operand: &Expression, precedence: u8) -> fmt::Result {
+    if operand.precedence() < precedence {
+        write!(f, "({})", operand)
+    } else {
+        write!(f, "{}", operand)
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, items: &[Expression]) -> fmt::Result {
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+
+        write!(f, "{}", item)?;
+    }
+
+    Ok(())
+}
+
+fn write_condition(f: &mut fmt::Formatter<'_>, condition: &Option<RollCondition>) -> fmt::Result {
+    match condition {
+        Some(condition) => write!(f, "{}", condition),
+        None => Ok(()),
+    }
+}
+
+/// Writes the value of a roll modifier, which is parenthesized unless it's an integer
+/// so that it can't run into a following modifier.
+fn write_modifier_value(f: &mut fmt::Formatter<'_>, value: &Expression) -> fmt::Result {
+    match value {
+        Expression::Literal(Literal::Integer(value)) if *value >= 0 => write!(f, "{}", value),
+        value => write!(f, "({})", value),
+    }
+}
+
+fn invalid_literal(pair: &Pair<'_, Rule>, message: &str) -> DocumentError {
+    let variant = ErrorVariant::CustomError {
+        message: message.to_owned(),
+    };
+
+    Error::new_from_span(variant, pair.as_span()).into()
+}
+
+fn unescape(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars();
+
+    while let Some(next_char) = chars.next() {
+        if next_char != '\\' {
+            output.push(next_char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('t') => output.push('\t'),
+            Some(escaped_char) => output.push(escaped_char),
+            None => unreachable!(),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::DocumentParser;
+    use pest::Parser as _;
+    use std::convert::TryInto as _;
+
+    fn parse(input: &str) -> Result<Expression, DocumentError> {
+        DocumentParser::parse(Rule::expression, input)?.try_into()
+    }
+
+    fn integer(value: i64) -> Box<Expression> {
+        Box::new(Expression::Literal(Literal::Integer(value)))
+    }
+
+    #[test]
+    fn parse_roll_binds_tighter_than_arithmetic() {
+        let expression = parse("{% 1d20 + $strength_mod %}").unwrap();
+
+        assert_eq!(
+            expression,
+            Expression::Binary {
+                operator: BinaryOperator::Add,
+                lhs: Box::new(Expression::Roll {
+                    count: integer(1),
+                    sides: integer(20),
+                    modifiers: vec![],
+                }),
+                rhs: Box::new(Expression::Variable("strength_mod".to_owned())),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_table_access() {
+        let expression = parse("{% global.ability_mods[self.strength] %}").unwrap();
+
+        assert_eq!(
+            expression,
+            Expression::Index {
+                target: Box::new(Expression::Field {
+                    target: Box::new(Expression::Identifier("global".to_owned())),
+                    field: "ability_mods".to_owned(),
+                }),
+                index: Box::new(Expression::Field {
+                    target: Box::new(Expression::Identifier("self".to_owned())),
+                    field: "strength".to_owned(),
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_ranges_and_lists() {
+        let expression = parse("{% 1d[2=..=4, 8..10] %}").unwrap();
+
+        assert_eq!(
+            expression,
+            Expression::Roll {
+                count: integer(1),
+                sides: Box::new(Expression::List(vec![
+                    Expression::Range {
+                        operator: RangeOperator::Inclusive,
+                        start: integer(2),
+                        end: integer(4),
+                    },
+                    Expression::Range {
+                        operator: RangeOperator::Exclusive,
+                        start: integer(8),
+                        end: integer(10),
+                    },
+                ])),
+                modifiers: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_function_calls_and_literals() {
+        let expression = parse(r#"{% max(-2.5, "a \"b\"", true) %}"#).unwrap();
+
+        assert_eq!(
+            expression,
+            Expression::Call {
+                function: "max".to_owned(),
+                arguments: vec![
+                    Expression::Unary {
+                        operator: UnaryOperator::Negate,
+                        operand: Box::new(Expression::Literal(Literal::Decimal(2.5))),
+                    },
+                    Expression::Literal(Literal::String("a \"b\"".to_owned())),
+                    Expression::Literal(Literal::Boolean(true)),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_roll_modifiers() {
+        let expression = parse("{% 4d6kh3r<2!p %}").unwrap();
+
+        assert_eq!(
+            expression,
+            Expression::Roll {
+                count: integer(4),
+                sides: integer(6),
+                modifiers: vec![
+                    RollModifier::Keep {
+                        selection: Selection::Highest,
+                        count: Some(*integer(3)),
+                    },
+                    RollModifier::Reroll {
+                        once: false,
+                        condition: RollCondition {
+                            operator: ConditionOperator::LessThan,
+                            value: *integer(2),
+                        },
+                    },
+                    RollModifier::Explode {
+                        kind: ExplodeKind::Penetrating,
+                        condition: None,
+                    },
+                ],
+            }
+        );
+
+        let inputs = &[
+            ("{% 2d20adv + 1d20dis %}", "2d20adv + 1d20dis"),
+            ("{% 4d6k %}", "4d6kh"),
+            ("{% 5d10kl2dh1dl$n %}", "5d10kl2dh1dl($n)"),
+            ("{% 3d6ro=1!!>=5 %}", "3d6ro1!!>=5"),
+            ("{% 1d(x)!(y) != 3 %}", "1d(x)!(y) != 3"),
+            ("{% 3d10!cs>(1 + 5) %}", "3d10!cs>(1 + 5)"),
+            ("{% 2d6d6 %}", "(2d6)d6"),
+            ("{% (1dx)d6kh %}", "(1dx)d6kh"),
+            ("{% (1d6)d(l) %}", "(1d6)dl"),
+            ("{% 5d10cs>=8cf %}", "5d10cs>=8cf"),
+            ("{% 4dF + 1d% %}", "4dF + 1d100"),
+            ("{% 3d(Boost)kh1 %}", "3d(Boost)kh1"),
+        ];
+
+        for (input, formatted) in inputs {
+            let expression = parse(input).unwrap();
+            assert_eq!(expression.to_string(), *formatted);
+            assert_eq!(parse(&format!("{{% {} %}}", formatted)).unwrap(), expression);
+        }
+    }
+
+    #[test]
+    fn parse_assignments() {
+        let expression = parse("{% self.hp -= 2d6 + $bonus %}").unwrap();
+
+        assert_eq!(
+            expression,
+            Expression::Assign {
+                operator: AssignmentOperator::Subtract,
+                target: Box::new(Expression::Field {
+                    target: Box::new(Expression::Identifier("self".to_owned())),
+                    field: "hp".to_owned(),
+                }),
+                value: Box::new(Expression::Binary {
+                    operator: BinaryOperator::Add,
+                    lhs: Box::new(Expression::Roll {
+                        count: integer(2),
+                        sides: integer(6),
+                        modifiers: Vec::new(),
+                    }),
+                    rhs: Box::new(Expression::Variable("bonus".to_owned())),
+                }),
+            }
+        );
+
+        let inputs = &[
+            ("{% targets[0].hp=3 %}", "targets[0].hp = 3"),
+            ("{% self.gold += 1d6 * 10 %}", "self.gold += 1d6 * 10"),
+            ("{% self.speed /= 2 %}", "self.speed /= 2"),
+            ("{% self.hp == 3 %}", "self.hp == 3"),
+        ];
+
+        for (input, formatted) in inputs {
+            let expression = parse(input).unwrap();
+            assert_eq!(expression.to_string(), *formatted);
+            assert_eq!(parse(&format!("{{% {} %}}", formatted)).unwrap(), expression);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_malformed_expressions() {
+        let inputs = &[
+            "{% 1d %}",
+            "{% 1 + %}",
+            "{% (1 %}",
+            "{% %}",
+            "{% 99999999999999999999 %}",
+            "{% 1d20r %}",
+            "{% 1 + self.hp = 2 %}",
+            "{% (self.hp -= 2) %}",
+        ];
 
-        Ok(Expression(expression))
+        for input in inputs {
+            assert!(parse(input).is_err(), "Expected expression to fail to parse: {}", input);
+        }
     }
 }
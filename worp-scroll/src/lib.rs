@@ -1,20 +1,31 @@
 mod definition;
 mod document;
+mod documentation;
 pub mod error;
 mod expression;
 mod link;
+mod location;
 mod parser;
+pub mod runtime;
 mod span;
 mod symbol;
+mod types;
+mod validation;
 mod variable;
 
 pub use definition::{Definition, DefinitionList};
 pub use document::Document;
-pub use expression::Expression;
-pub use link::{LabeledTarget, Link, LinkTarget};
-pub use span::{Span, SpanList};
-pub use symbol::Symbol;
-pub use variable::{Variable, VariableList};
+pub use documentation::{Documentation, Metadata, Parameter};
+pub use expression::{
+    AssignmentOperator, BinaryOperator, ConditionOperator, ExplodeKind, Expression, Literal, RangeOperator,
+    RollCondition, RollModifier, Selection, UnaryOperator,
+};
+pub use link::{LabeledTarget, Link, LinkTarget, TargetList};
+pub use location::Location;
+pub use span::{Span, SpanKind, SpanList};
+pub use symbol::{Symbol, SymbolKind};
+pub use types::{Schema, Type};
+pub use variable::{Prompt, PromptType, Variable, VariableKind, VariableList};
 
 #[macro_use]
 #[doc(hidden)]
@@ -49,4 +60,231 @@ mod test {
             println!("{:?}", _result);
         }
     }
+
+    #[test]
+    fn test_invalid_expressions_fail_to_parse() {
+        let inputs = &["{% 1d20 + %}", "$x := {% global[ %}\n\ntext", "*{% (1 %}*"];
+
+        for input in inputs {
+            assert!(Document::try_from_str(input).is_err());
+        }
+    }
+
+    #[test]
+    fn test_nodes_keep_their_locations() {
+        let input = "abc {% 1 %}\n[Go](#sub)\n\n== #sub ==\n$x := {% 2 %}\n\n$x";
+        let document = Document::try_from_str(input).unwrap();
+
+        let expression = &document.main_macro.body[1];
+        assert_eq!(&input[expression.location.start..expression.location.end], "{% 1 %}");
+        assert_eq!((expression.location.line, expression.location.column), (1, 5));
+
+        let link = match &document.main_macro.body[3].kind {
+            SpanKind::Link(link) => link,
+            _ => unreachable!(),
+        };
+        assert_eq!((link.location.line, link.location.column), (2, 1));
+        assert!(matches!(&link.target, LinkTarget::Target(symbol) if symbol.location.column == 6));
+
+        let sub_macro = &document.sub_macros[0];
+        let name = sub_macro.name.as_ref().unwrap();
+        assert_eq!((name.location.line, name.location.column), (4, 4));
+
+        let variable = &sub_macro.variables[0];
+        assert_eq!(&input[variable.location.start..variable.location.end], "$x := {% 2 %}");
+        assert_eq!((variable.name.location.line, variable.name.location.column), (5, 1));
+    }
+
+    #[test]
+    fn test_documentation_is_kept() {
+        let document = Document::try_from_str(include_str!("../test/data/long_sword_basic_attack.txt")).unwrap();
+        assert_eq!(
+            document.main_macro.documentation.lines,
+            vec!["Long sword is enchanted to do +2 damage but includes no bonus to hit"]
+        );
+        assert!(document.sub_macros[0].documentation.is_empty());
+
+        let input = "> Main macro\n>\n>   Second paragraph\nbody\n\n== #sub ==\n> Sub-macro docs\n$x := {% 1 %}\n\n$x";
+        let document = Document::try_from_str(input).unwrap();
+        assert_eq!(
+            document.main_macro.documentation.text(),
+            "Main macro\n\nSecond paragraph"
+        );
+        assert_eq!(document.sub_macros[0].documentation.lines, vec!["Sub-macro docs"]);
+        assert_eq!(document.sub_macros[0].documentation.location.line, 7);
+    }
+
+    #[test]
+    fn test_documentation_tags_are_parsed() {
+        let input = "> Swing a sword.\n> @tag combat, melee\n> @tag weapon\n> @param $bonus Situational bonus to hit\n> @author Gimli\n> @system dnd5e\n> @since 1.0\n{% 1d20 %}";
+        let document = Document::try_from_str(input).unwrap();
+        let metadata = &document.main_macro.metadata;
+
+        assert_eq!(document.main_macro.documentation.lines, vec!["Swing a sword."]);
+        assert_eq!(metadata.tags, vec!["combat", "melee", "weapon"]);
+        assert!(metadata.has_tag("Combat") && !metadata.has_tag("ranged"));
+        assert_eq!(
+            metadata.params,
+            vec![Parameter {
+                name: "bonus".to_owned(),
+                description: "Situational bonus to hit".to_owned(),
+            }]
+        );
+        assert_eq!(metadata.authors, vec!["Gimli"]);
+        assert!(metadata.has_system("DND5E"));
+        assert_eq!(metadata.other["since"], vec!["1.0"]);
+    }
+
+    #[test]
+    fn test_formatting_round_trips() {
+        let inputs = &[
+            include_str!("../test/data/nonsense.txt"),
+            include_str!("../test/data/more_nonsense.txt"),
+            include_str!("../test/data/long_sword_basic_attack.txt"),
+            include_str!("../test/data/long_sword_multiple_attack.txt"),
+            include_str!("../test/data/eblast.txt"),
+        ];
+
+        for input in inputs {
+            let document = Document::try_from_str(input).unwrap();
+            let formatted = document.to_string();
+            let reparsed = Document::try_from_str(&formatted).unwrap();
+
+            assert_eq!(without_locations(&document), without_locations(&reparsed));
+            assert_eq!(formatted, reparsed.to_string());
+        }
+    }
+
+    /// The debug form of a document's macros, without the locations that formatting moves around.
+    fn without_locations(document: &Document) -> String {
+        let mut debug = format!("{:?} {:?}", document.main_macro, document.sub_macros);
+        while let Some(start) = debug.find("location: Location {") {
+            let end = start + debug[start..].find('}').unwrap() + 1;
+            debug.replace_range(start..end, "");
+        }
+
+        debug
+    }
+
+    #[test]
+    fn test_each_target_blocks_are_parsed() {
+        let input = "Hits:\n{%each   target%}*{% target.name %}* takes {% 7 %}\n{%end%}Done";
+        let document = Document::try_from_str(input).unwrap();
+
+        let spans = match &document.main_macro.body[2].kind {
+            SpanKind::EachTarget(spans) => spans,
+            kind => panic!("expected an each target block, found {:?}", kind),
+        };
+        assert_eq!(spans.len(), 4);
+        assert!(matches!(&document.main_macro.body[3].kind, SpanKind::RawText(text) if text == "Done"));
+        assert_eq!(
+            document.to_string(),
+            "Hits:\n{% each target %}*{% target.name %}* takes {% 7 %}\n{% end %}Done"
+        );
+
+        assert!(Document::try_from_str("{% each target %}unclosed").is_err());
+    }
+
+    #[test]
+    fn test_conditional_blocks_are_parsed() {
+        let input =
+            "{%if $roll==20%}*Critical hit!* {% if true %}x{% end %}{%else%}Hit{% end %}\n{% if false %}never{% end %}";
+        let document = Document::try_from_str(input).unwrap();
+
+        match &document.main_macro.body[0].kind {
+            SpanKind::Conditional {
+                condition,
+                body,
+                otherwise: Some(otherwise),
+            } => {
+                assert_eq!(condition.to_string(), "$roll == 20");
+                assert!(matches!(&body[2].kind, SpanKind::Conditional { otherwise: None, .. }));
+                assert!(matches!(&otherwise[0].kind, SpanKind::RawText(text) if text == "Hit"));
+            }
+            kind => panic!("expected a conditional block, found {:?}", kind),
+        }
+
+        assert_eq!(
+            document.to_string(),
+            "{% if $roll == 20 %}*Critical hit!* {% if true %}x{% end %}{% else %}Hit{% end %}\n\
+             {% if false %}never{% end %}"
+        );
+
+        assert!(Document::try_from_str("{% if true %}unclosed").is_err());
+    }
+
+    #[test]
+    fn test_prompts_are_parsed() {
+        let input = "$bonus:=prompt \"Situational \\\"bonus\\\"\" : integer=-2\n$mode := prompt \"Mode\": string [\"melee\",\"ranged\"]\n$x := {% $bonus %}\n\n$mode";
+        let document = Document::try_from_str(input).unwrap();
+
+        let prompt = match &document.main_macro.variables[0].kind {
+            VariableKind::Prompt(prompt) => prompt,
+            kind => panic!("expected a prompt, found {:?}", kind),
+        };
+        assert_eq!(prompt.label, "Situational \"bonus\"");
+        assert_eq!(prompt.value_type, PromptType::Integer);
+        assert_eq!(prompt.default, Some(Literal::Integer(-2)));
+        assert!(prompt.choices.is_empty());
+
+        assert_eq!(
+            document.to_string(),
+            "$bonus := prompt \"Situational \\\"bonus\\\"\": integer = -2\n\
+             $mode  := prompt \"Mode\": string [\"melee\", \"ranged\"]\n\
+             $x     := {% $bonus %}\n\
+             \n\
+             $mode"
+        );
+    }
+
+    #[test]
+    fn test_formatting_is_canonical() {
+        let input = "> Attack!\n>\n> @param $bonus Bonus to hit\n> @tag combat\n$bonus:={%2%}\n$attack := {%1d20+$bonus*(2-1)%}\n\n$attack [Damage](\"Normal\":#damage,\"Critical\" : #crit)\n==#damage==\n{% (1d8)d6+-1 %}\n==   #crit   ==\n{% [1,2][0]..=$bonus %}";
+        let document = Document::try_from_str(input).unwrap();
+
+        assert_eq!(
+            document.to_string(),
+            "> Attack!\n\
+             >\n\
+             > @param $bonus Bonus to hit\n\
+             > @tag combat\n\
+             \n\
+             $bonus  := {% 2 %}\n\
+             $attack := {% 1d20 + $bonus * (2 - 1) %}\n\
+             \n\
+             $attack [Damage](\"Normal\": #damage, \"Critical\": #crit)\n\
+             \n\
+             == #damage ==\n\
+             {% (1d8)d6 + -1 %}\n\
+             \n\
+             == #crit ==\n\
+             {% [1, 2][0]..=$bonus %}"
+        );
+    }
+
+    #[test]
+    fn test_reserved_characters_can_be_escaped() {
+        let input = "HP \\- 5 for snake\\_case \\#macro \\$1 \\[x] \\{% 1 %} \\\\ C:\\dir *\\*bold\\**";
+        let document = Document::try_from_str(input).unwrap();
+        let body = &document.main_macro.body;
+
+        assert!(matches!(
+            &body[0].kind,
+            SpanKind::RawText(text) if text == "HP - 5 for snake_case #macro $1 [x] {% 1 %} \\ C:\\dir "
+        ));
+        assert!(matches!(
+            &body[1].kind,
+            SpanKind::BoldText(spans) if matches!(&spans[0].kind, SpanKind::RawText(text) if text == "*bold*")
+        ));
+
+        let formatted = document.to_string();
+        assert_eq!(
+            formatted,
+            "HP \\- 5 for snake\\_case \\#macro \\$1 \\[x] \\{% 1 %} \\\\ C:\\\\dir *\\*bold\\**"
+        );
+        assert!(matches!(
+            &Document::try_from_str(&formatted).unwrap().main_macro.body[0].kind,
+            SpanKind::RawText(text) if text.ends_with(" \\ C:\\dir ")
+        ));
+    }
 }
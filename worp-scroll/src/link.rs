@@ -1,8 +1,9 @@
 use super::error::DocumentError;
-use crate::{next_pair, parser::Rule};
-use pest::iterators::Pairs;
+use crate::{next_pair, parser::Rule, Location, Symbol};
+use pest::iterators::{Pair, Pairs};
 use std::{
     convert::{TryFrom, TryInto},
+    fmt,
     ops::{Deref, DerefMut},
 };
 
@@ -10,25 +11,32 @@ use std::{
 pub struct Link {
     pub label: String,
     pub target: LinkTarget,
+    pub location: Location,
 }
 
-impl TryFrom<Pairs<'_, Rule>> for Link {
+impl TryFrom<Pair<'_, Rule>> for Link {
     type Error = DocumentError;
 
-    fn try_from(mut link_pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
+    fn try_from(link_pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let location = link_pair.as_span().into();
+        let mut link_pairs = link_pair.into_inner();
         let label = next_pair!(link_pairs => Rule::macro_link_label).as_str().to_string();
         let target = next_pair!(link_pairs => Rule::macro_link_target)
             .into_inner()
             .try_into()?;
-        let link = Link { label, target };
+        let link = Link {
+            label,
+            target,
+            location,
+        };
 
         Ok(link)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum LinkTarget {
-    Target(String),
+    Target(Symbol),
     TargetList(TargetList),
 }
 
@@ -43,7 +51,7 @@ impl TryFrom<Pairs<'_, Rule>> for LinkTarget {
 
         let target = match rule {
             Rule::macro_name => {
-                let macro_name = next_pair!(link_target_pairs => Rule::macro_name).as_str().to_owned();
+                let macro_name = next_pair!(link_target_pairs => Rule::macro_name).try_into()?;
                 LinkTarget::Target(macro_name)
             }
             Rule::macro_link_target_set => {
@@ -52,15 +60,18 @@ impl TryFrom<Pairs<'_, Rule>> for LinkTarget {
 
                 for link_target_set_pair in link_target_set_pairs {
                     if link_target_set_pair.as_rule() == Rule::macro_link_target_with_label {
+                        let location = link_target_set_pair.as_span().into();
                         let mut link_target_with_label_pairs = link_target_set_pair.into_inner();
                         let label = next_pair!(link_target_with_label_pairs => Rule::macro_link_target_label)
                             .as_str()
                             .to_owned();
-                        let target = next_pair!(link_target_with_label_pairs => Rule::macro_name)
-                            .as_str()
-                            .to_owned();
+                        let target = next_pair!(link_target_with_label_pairs => Rule::macro_name).try_into()?;
 
-                        labeled_targets.push(LabeledTarget { label, target });
+                        labeled_targets.push(LabeledTarget {
+                            label,
+                            target,
+                            location,
+                        });
                     } else {
                         unreachable!()
                     }
@@ -75,7 +86,7 @@ impl TryFrom<Pairs<'_, Rule>> for LinkTarget {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TargetList(pub Vec<LabeledTarget>);
 
 impl Deref for TargetList {
@@ -92,8 +103,34 @@ impl DerefMut for TargetList {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LabeledTarget {
-    label: String,
-    target: String,
+    pub label: String,
+    pub target: Symbol,
+    pub location: Location,
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]({})", self.label, self.target)
+    }
+}
+
+impl fmt::Display for LinkTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkTarget::Target(target) => write!(f, "{}", target),
+            LinkTarget::TargetList(targets) => {
+                for (index, labeled_target) in targets.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "\"{}\": {}", labeled_target.label, labeled_target.target)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
 }
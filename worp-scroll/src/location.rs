@@ -0,0 +1,32 @@
+use std::fmt;
+
+/// The position of a node within the source of a macro document.
+///
+/// Offsets are in bytes and the range is half-open, while lines and columns start at one and
+/// refer to the start of the node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl From<pest::Span<'_>> for Location {
+    fn from(span: pest::Span<'_>) -> Self {
+        let (line, column) = span.start_pos().line_col();
+
+        Location {
+            start: span.start(),
+            end: span.end(),
+            line,
+            column,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
@@ -16,7 +16,7 @@ mod test {
             for input in $in {
                 let len = match DocumentParser::parse($rule, input) {
                     Ok(rule) => rule.last().unwrap().as_span().end(),
-                    Err(err) => panic!(format!("{}", err)),
+                    Err(err) => panic!("{}", err),
                 };
 
                 assert_eq!(
@@ -0,0 +1,482 @@
+use super::{
+    dice::{self, Faces, Modifier},
+    evaluator, DiceRng, Distribution, Environment, Evaluator, Limits, Value,
+};
+use crate::{
+    error::EvaluationError,
+    expression::{BinaryOperator, Expression, UnaryOperator},
+};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+/// The probability of each result, while an expression is being analyzed.
+type Outcomes = BTreeMap<i64, f64>;
+
+/// The most work done analyzing a single expression before exact analysis is abandoned, where combining a pair of
+/// results is one unit of work and rolling a die while enumerating the sequences of a roll is another.
+const MAX_WORK: u64 = 2_000_000;
+/// The most dice in each sequence enumerated for a roll with modifiers.
+const MAX_ENUMERATED_DICE: i64 = 100;
+const DEFAULT_SAMPLES: usize = 10_000;
+
+/// Computes the probability distribution of a DICE expression without showing any of its rolls,
+/// such as for balancing homebrew content.
+///
+/// Expressions built from rolls, arithmetic and known values are analyzed exactly. Anything else, such as exploding
+/// dice or functions of rolls, is instead estimated by rolling the expression many times and counting the results.
+pub struct Analyzer<'a> {
+    rng: &'a mut dyn DiceRng,
+    samples: usize,
+    limits: Limits,
+    /// The work left before the current expression is sampled instead of analyzed exactly.
+    remaining_work: u64,
+}
+
+impl<'a> Analyzer<'a> {
+    pub fn new(rng: &'a mut dyn DiceRng) -> Self {
+        Self {
+            rng,
+            samples: DEFAULT_SAMPLES,
+            limits: Limits::default(),
+            remaining_work: MAX_WORK,
+        }
+    }
+
+    /// Limits the dice rolled and steps taken by each evaluation of the expression, instead of using the default
+    /// limits.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets how many times an expression is rolled when it can't be analyzed exactly, which is 10,000 by default.
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Computes the distribution of the integer results of an expression.
+    ///
+    /// ```
+    /// # use worp_scroll::{Document, SpanKind, runtime::{Analyzer, Environment, Value}};
+    /// # use rand::{rngs::StdRng, SeedableRng as _};
+    /// let document = Document::try_from_str("{% 4d6kh3 + $mod %}")?;
+    /// let expression = match &document.main_macro.body[0].kind {
+    ///     SpanKind::Expression(expression) => expression,
+    ///     _ => unreachable!(),
+    /// };
+    ///
+    /// let mut environment = Environment::new();
+    /// environment.set_variable("mod", Value::Integer(2));
+    ///
+    /// let mut rng = StdRng::seed_from_u64(0);
+    /// let distribution = Analyzer::new(&mut rng).analyze(expression, &environment)?;
+    /// assert!(distribution.is_exact());
+    /// assert_eq!((distribution.min(), distribution.max()), (5, 20));
+    /// assert!((distribution.probability_at_least(20) - 21.0 / 1296.0).abs() < 1e-9);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn analyze(
+        &mut self,
+        expression: &Expression,
+        environment: &Environment,
+    ) -> Result<Distribution, EvaluationError> {
+        self.remaining_work = MAX_WORK;
+
+        match self.analyze_exactly(expression, environment)? {
+            Some(outcomes) => Ok(Distribution::new(outcomes, true)),
+            None => self.sample(expression, environment),
+        }
+    }
+
+    /// Rolls the expression repeatedly, estimating the probability of each result by how often it was rolled.
+    fn sample(&mut self, expression: &Expression, environment: &Environment) -> Result<Distribution, EvaluationError> {
+        let mut counts = BTreeMap::new();
+
+        for _ in 0..self.samples {
+            let value = Evaluator::new(&mut *self.rng)
+                .with_limits(self.limits)
+                .evaluate(expression, environment)?
+                .as_integer()?;
+            *counts.entry(value).or_insert(0usize) += 1;
+        }
+
+        let outcomes = counts
+            .into_iter()
+            .map(|(value, count)| (value, count as f64 / self.samples as f64))
+            .collect();
+
+        Ok(Distribution::new(outcomes, false))
+    }
+
+    /// Computes the exact distribution of an expression, or `None` if it can't be computed in a reasonable time.
+    fn analyze_exactly(
+        &mut self,
+        expression: &Expression,
+        environment: &Environment,
+    ) -> Result<Option<Outcomes>, EvaluationError> {
+        if rolls_in(expression) == 0 {
+            let value = Evaluator::new(&mut *self.rng)
+                .with_limits(self.limits)
+                .evaluate(expression, environment)?;
+            return Ok(Some(certain(value.as_integer()?)));
+        }
+
+        let outcomes = match expression {
+            Expression::Unary {
+                operator: operator @ UnaryOperator::Negate,
+                operand,
+            } => match self.analyze_exactly(operand, environment)? {
+                Some(operand) => self.combine(&operand, &certain(0), |value, _| {
+                    evaluator::unary(*operator, Value::Integer(value))
+                })?,
+                None => None,
+            },
+            Expression::Binary {
+                operator:
+                    operator @ (BinaryOperator::Add
+                    | BinaryOperator::Subtract
+                    | BinaryOperator::Multiply
+                    | BinaryOperator::Divide
+                    | BinaryOperator::Remainder),
+                lhs,
+                rhs,
+            } => {
+                let lhs = self.analyze_exactly(lhs, environment)?;
+                let rhs = self.analyze_exactly(rhs, environment)?;
+
+                match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) => self.combine(&lhs, &rhs, |lhs, rhs| {
+                        evaluator::binary(*operator, Value::Integer(lhs), Value::Integer(rhs))
+                    })?,
+                    _ => None,
+                }
+            }
+            Expression::Roll {
+                count,
+                sides,
+                modifiers,
+            } if rolls_in(expression) == rolls_in(count) + 1 => {
+                let counts = match self.analyze_exactly(count, environment)? {
+                    Some(counts) => counts,
+                    None => return Ok(None),
+                };
+
+                let mut evaluator = Evaluator::new(&mut *self.rng).with_limits(self.limits);
+                let faces = evaluator.evaluate_faces(sides, environment)?;
+                let modifiers = modifiers
+                    .iter()
+                    .map(|modifier| evaluator.evaluate_modifier(modifier, environment))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.roll_outcomes(&counts, &faces, &modifiers)?
+            }
+            _ => None,
+        };
+
+        Ok(outcomes)
+    }
+
+    /// Takes some of the work left for exact analysis, returning `false` if there isn't enough left.
+    fn spend(&mut self, work: u64) -> bool {
+        match self.remaining_work.checked_sub(work) {
+            Some(remaining_work) => {
+                self.remaining_work = remaining_work;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Combines every pair of results from two independent distributions.
+    fn combine(
+        &mut self,
+        lhs: &Outcomes,
+        rhs: &Outcomes,
+        operation: impl Fn(i64, i64) -> Result<Value, EvaluationError>,
+    ) -> Result<Option<Outcomes>, EvaluationError> {
+        if !self.spend((lhs.len() as u64).saturating_mul(rhs.len() as u64)) {
+            return Ok(None);
+        }
+
+        let mut outcomes = Outcomes::new();
+
+        for (lhs, lhs_probability) in lhs {
+            for (rhs, rhs_probability) in rhs {
+                let value = operation(*lhs, *rhs)?.as_integer()?;
+                *outcomes.entry(value).or_default() += lhs_probability * rhs_probability;
+            }
+        }
+
+        Ok(Some(outcomes))
+    }
+
+    /// Computes the distribution of a roll's total, given the distribution of its number of dice.
+    /// Plain rolls are built up one die at a time, once for the largest count, while rolls with modifiers enumerate
+    /// every sequence of dice. Rerolls and explosions can roll any number of dice, so they can't be analyzed exactly.
+    fn roll_outcomes(
+        &mut self,
+        counts: &Outcomes,
+        faces: &Faces,
+        modifiers: &[Modifier],
+    ) -> Result<Option<Outcomes>, EvaluationError> {
+        if let Some((&count, _)) = counts.iter().find(|(count, _)| **count < 0) {
+            return Err(EvaluationError::InvalidDiceCount(count));
+        }
+
+        let mut outcomes = Outcomes::new();
+
+        if modifiers.is_empty() {
+            let die = enumerate_rolls(1, faces, modifiers)?;
+            let mut total = certain(0);
+            let mut dice = 0;
+
+            for (&count, probability) in counts {
+                while dice < count {
+                    total = match self.combine(&total, &die, |lhs, rhs| {
+                        evaluator::binary(BinaryOperator::Add, Value::Integer(lhs), Value::Integer(rhs))
+                    })? {
+                        Some(total) => total,
+                        None => return Ok(None),
+                    };
+                    dice += 1;
+                }
+
+                for (value, rolled_probability) in &total {
+                    *outcomes.entry(*value).or_default() += probability * rolled_probability;
+                }
+            }
+
+            return Ok(Some(outcomes));
+        }
+
+        for (&count, probability) in counts {
+            match self.enumerated_roll_outcomes(count, faces, modifiers)? {
+                Some(rolled) => {
+                    for (value, rolled_probability) in rolled {
+                        *outcomes.entry(value).or_default() += probability * rolled_probability;
+                    }
+                }
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(outcomes))
+    }
+
+    /// Computes the distribution of a roll with modifiers by enumerating every sequence of its dice.
+    fn enumerated_roll_outcomes(
+        &mut self,
+        count: i64,
+        faces: &Faces,
+        modifiers: &[Modifier],
+    ) -> Result<Option<Outcomes>, EvaluationError> {
+        let mut draws = count;
+        for modifier in modifiers {
+            match modifier {
+                Modifier::Reroll { .. } | Modifier::Explode { .. } => return Ok(None),
+                Modifier::Advantage | Modifier::Disadvantage => draws = count.saturating_mul(2),
+                _ => {}
+            }
+        }
+
+        // One-sided dice only ever have a single sequence, so the number of dice is limited as well.
+        if draws > MAX_ENUMERATED_DICE {
+            return Ok(None);
+        }
+
+        let work = u32::try_from(draws)
+            .ok()
+            .and_then(|draws| faces.len().checked_pow(draws))
+            .and_then(|sequences| sequences.checked_mul(draws.max(1) as u64));
+        match work {
+            Some(work) if self.spend(work) => Ok(Some(enumerate_rolls(count, faces, modifiers)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Counts the rolls in an expression, including the expression itself.
+fn rolls_in(expression: &Expression) -> usize {
+    let mut rolls = 0;
+    expression.visit(&mut |expression| {
+        if let Expression::Roll { .. } = expression {
+            rolls += 1;
+        }
+    });
+
+    rolls
+}
+
+fn certain(value: i64) -> Outcomes {
+    let mut outcomes = Outcomes::new();
+    outcomes.insert(value, 1.0);
+
+    outcomes
+}
+
+/// Rolls the dice with every possible sequence of faces, each of which is equally likely.
+fn enumerate_rolls(count: i64, faces: &Faces, modifiers: &[Modifier]) -> Result<Outcomes, EvaluationError> {
+    let draws = match modifiers
+        .iter()
+        .any(|modifier| matches!(modifier, Modifier::Advantage | Modifier::Disadvantage))
+    {
+        true => count * 2,
+        false => count,
+    };
+    let sides = faces.len();
+    let probability = 1.0 / (sides as f64).powi(draws as i32);
+    let mut sequence = vec![1; draws as usize];
+    let mut outcomes = Outcomes::new();
+
+    loop {
+        let (roll, _) = dice::roll(&mut Script(sequence.iter()), count, faces, modifiers, u64::MAX)?;
+        *outcomes.entry(roll.total()?).or_default() += probability;
+
+        // Advances to the next sequence, counting in base `sides` with the first die changing fastest.
+        match sequence.iter().position(|face| *face < sides) {
+            Some(index) => {
+                sequence[index] += 1;
+                sequence[..index].iter_mut().for_each(|face| *face = 1);
+            }
+            None => return Ok(outcomes),
+        }
+    }
+}
+
+/// Rolls a predetermined sequence of faces.
+struct Script<'a>(std::slice::Iter<'a, u64>);
+
+impl DiceRng for Script<'_> {
+    fn roll_die(&mut self, _sides: u64) -> u64 {
+        self.0.next().copied().unwrap_or_else(|| unreachable!())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{runtime::Limits, Document, SpanKind};
+    use rand::{rngs::StdRng, SeedableRng as _};
+
+    fn analyze(input: &str, environment: &Environment) -> Result<Distribution, EvaluationError> {
+        let document = Document::try_from_str(&format!("{{% {} %}}", input)).unwrap();
+        let expression = match &document.main_macro.body[0].kind {
+            SpanKind::Expression(expression) => expression,
+            _ => unreachable!(),
+        };
+
+        let mut rng = StdRng::seed_from_u64(0);
+        Analyzer::new(&mut rng).analyze(expression, environment)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, found {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn analyze_plain_rolls() {
+        let environment = Environment::new();
+
+        let distribution = analyze("2d6", &environment).unwrap();
+        assert!(distribution.is_exact());
+        assert_eq!((distribution.min(), distribution.max()), (2, 12));
+        assert_close(distribution.probability(7), 6.0 / 36.0);
+        assert_close(distribution.mean(), 7.0);
+        assert_close(distribution.variance(), 35.0 / 6.0);
+
+        let distribution = analyze("1d6 - 1d6", &environment).unwrap();
+        assert_close(distribution.mean(), 0.0);
+        assert_close(distribution.probability_at_most(-5), 1.0 / 36.0);
+
+        let distribution = analyze("(1d4)d6", &environment).unwrap();
+        assert_eq!((distribution.min(), distribution.max()), (1, 24));
+        assert_close(distribution.mean(), 2.5 * 3.5);
+
+        assert_close(analyze("100d6", &environment).unwrap().mean(), 350.0);
+        assert_close(analyze("-1d%", &environment).unwrap().mean(), -50.5);
+    }
+
+    #[test]
+    fn analyze_rolls_with_modifiers() {
+        let mut environment = Environment::new();
+        environment.set_variable("mod", Value::Integer(2));
+
+        let distribution = analyze("4d6kh3 + $mod", &environment).unwrap();
+        assert!(distribution.is_exact());
+        assert_close(distribution.mean(), 15869.0 / 1296.0 + 2.0);
+        assert_close(distribution.probability_at_least(18), 169.0 / 1296.0);
+
+        let distribution = analyze("1d20adv", &environment).unwrap();
+        assert_close(
+            distribution.probability_at_least(20),
+            1.0 - (19.0 / 20.0) * (19.0 / 20.0),
+        );
+
+        let distribution = analyze("5d10cs>=8", &environment).unwrap();
+        assert_close(distribution.mean(), 5.0 * 0.3);
+    }
+
+    #[test]
+    fn analyze_falls_back_to_sampling() {
+        let environment = Environment::new();
+
+        let distribution = analyze("1d6!", &environment).unwrap();
+        assert!(!distribution.is_exact());
+        assert_eq!(distribution.min(), 1);
+        assert!((distribution.mean() - 4.2).abs() < 0.1);
+
+        let distribution = analyze("max(1d20, 1d20)", &environment).unwrap();
+        assert!(!distribution.is_exact());
+        assert!((distribution.probability_at_least(20) - 0.0975).abs() < 0.02);
+
+        assert_eq!(
+            analyze("10 / (1d2 - 1)", &environment),
+            Err(EvaluationError::DivisionByZero)
+        );
+        assert_eq!(
+            analyze("1000000000d1kh1", &environment),
+            Err(EvaluationError::DiceLimitExceeded(Limits::default().max_dice_per_roll))
+        );
+    }
+
+    #[test]
+    fn analyze_limits_the_work_done() {
+        let environment = Environment::new();
+        let expression = |input: &str| {
+            let document = Document::try_from_str(&format!("{{% {} %}}", input)).unwrap();
+            match &document.main_macro.body[0].kind {
+                SpanKind::Expression(expression) => expression.clone(),
+                _ => unreachable!(),
+            }
+        };
+
+        // Each count reuses the sums of the counts before it, so a few hundred dice are still analyzed exactly.
+        let distribution = analyze("(1d300)d6", &environment).unwrap();
+        assert!(distribution.is_exact());
+        assert_eq!((distribution.min(), distribution.max()), (1, 1800));
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let distribution = Analyzer::new(&mut rng)
+            .with_samples(100)
+            .analyze(&expression("(1d1000)d6"), &environment)
+            .unwrap();
+        assert!(!distribution.is_exact());
+
+        let limits = Limits {
+            max_dice_per_roll: 10,
+            ..Limits::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(
+            Analyzer::new(&mut rng)
+                .with_limits(limits)
+                .analyze(&expression("20d6!"), &environment),
+            Err(EvaluationError::DiceLimitExceeded(10))
+        );
+    }
+}
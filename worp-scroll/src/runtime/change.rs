@@ -0,0 +1,16 @@
+use super::Value;
+use serde::{Deserialize, Serialize};
+
+/// A change to one of a token's attributes, made by an assignment such as `self.hp -= 8`.
+///
+/// Changes aren't applied by the macro itself, they're returned once it finishes so the game can decide whether to
+/// allow them, log them and apply them, while keeping the previous value to undo them.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AttributeChange {
+    /// The `id` of the token's table, such as the table of `self` or of one of the `targets`.
+    pub token: String,
+    pub attribute: String,
+    /// The attribute's value before the change, or `None` if the token didn't have the attribute.
+    pub previous: Option<Value>,
+    pub value: Value,
+}
@@ -0,0 +1,16 @@
+use super::Value;
+
+/// The source of the pre-defined tables that expressions can reference, such as the game's data for `global`
+/// and the selected token's data for `self`, so the same macro can be run against any token.
+pub trait EvaluationContext {
+    /// The game-wide table, `global`.
+    fn global_table(&self) -> Value;
+
+    /// The table of the token the macro is being run for, `self`, if a token is selected.
+    fn self_table(&self) -> Option<Value>;
+
+    /// The tables of the tokens targeted by the macro, `targets`, in the order they were targeted.
+    fn target_tables(&self) -> Vec<Value> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,14 @@
+use super::Environment;
+use serde::{Deserialize, Serialize};
+
+/// Everything needed to execute the sub-macros targeted by a link at a later time, possibly on another machine.
+///
+/// A continuation records the document the link belongs to, along with the environment its main macro was executed
+/// in, so the targeted sub-macro sees the same values for the main macro's variables, such as dice that were
+/// already rolled.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Continuation {
+    /// The source of the document that the link belongs to, as it was written.
+    pub source: String,
+    pub environment: Environment,
+}
@@ -0,0 +1,373 @@
+use super::{DiceRng, Die, DieStatus, Outcome, Roll};
+use crate::{
+    error::EvaluationError,
+    expression::{ConditionOperator, ExplodeKind, Selection},
+};
+use std::{collections::BTreeMap, convert::TryFrom, ops::Range};
+
+/// The faces of the dice being rolled, either numbered from one up to a number of sides or taken from a list.
+pub(crate) enum Faces {
+    Sides(i64),
+    List(Vec<Face>),
+}
+
+/// A face of a die rolled from a list, which shows a number and any number of symbols.
+pub(crate) struct Face {
+    pub value: i64,
+    pub symbols: BTreeMap<String, i64>,
+}
+
+impl Face {
+    pub fn number(value: i64) -> Self {
+        Self {
+            value,
+            symbols: BTreeMap::new(),
+        }
+    }
+}
+
+impl Faces {
+    /// The faces of a Fate or Fudge die, which shows -1, 0 or +1.
+    pub fn fate() -> Self {
+        Faces::List((-1..=1).map(Face::number).collect())
+    }
+
+    fn roll(&self, rng: &mut dyn DiceRng) -> Die {
+        match self {
+            Faces::Sides(sides) => Die::kept(rng.roll_die(*sides as u64) as i64),
+            Faces::List(faces) => {
+                let face = &faces[rng.roll_die(faces.len() as u64) as usize - 1];
+                let mut die = Die::kept(face.value);
+                die.symbols = face.symbols.clone();
+
+                die
+            }
+        }
+    }
+
+    /// The number of faces on the die.
+    pub fn len(&self) -> u64 {
+        match self {
+            Faces::Sides(sides) => *sides as u64,
+            Faces::List(faces) => faces.len() as u64,
+        }
+    }
+
+    fn lowest(&self) -> i64 {
+        match self {
+            Faces::Sides(_) => 1,
+            Faces::List(faces) => faces.iter().map(|face| face.value).min().unwrap_or_default(),
+        }
+    }
+
+    fn highest(&self) -> i64 {
+        match self {
+            Faces::Sides(sides) => *sides,
+            Faces::List(faces) => faces.iter().map(|face| face.value).max().unwrap_or_default(),
+        }
+    }
+
+    /// Whether every face of the die meets the condition.
+    fn all_match(&self, condition: &Condition) -> bool {
+        match self {
+            Faces::Sides(sides) => match condition.operator {
+                ConditionOperator::Equal => *sides == 1 && condition.value == 1,
+                _ => condition.matches(1) && condition.matches(*sides),
+            },
+            Faces::List(faces) => faces.iter().all(|face| condition.matches(face.value)),
+        }
+    }
+
+    /// Every symbol shown on any face of the die.
+    fn symbols(&self) -> impl Iterator<Item = &String> {
+        let faces = match self {
+            Faces::Sides(_) => &[][..],
+            Faces::List(faces) => &faces[..],
+        };
+
+        faces.iter().flat_map(|face| face.symbols.keys())
+    }
+}
+
+/// A roll modifier whose counts and conditions have already been evaluated.
+pub(crate) enum Modifier {
+    Keep(Selection, i64),
+    Drop(Selection, i64),
+    Advantage,
+    Disadvantage,
+    Reroll {
+        once: bool,
+        condition: Condition,
+    },
+    Explode {
+        kind: ExplodeKind,
+        condition: Option<Condition>,
+    },
+    CountSuccesses(Option<Condition>),
+    CountFailures(Option<Condition>),
+}
+
+pub(crate) struct Condition {
+    pub operator: ConditionOperator,
+    pub value: i64,
+}
+
+impl Condition {
+    fn matches(&self, value: i64) -> bool {
+        match self.operator {
+            ConditionOperator::Equal => value == self.value,
+            ConditionOperator::LessThan => value < self.value,
+            ConditionOperator::LessThanOrEqual => value <= self.value,
+            ConditionOperator::GreaterThan => value > self.value,
+            ConditionOperator::GreaterThanOrEqual => value >= self.value,
+        }
+    }
+}
+
+impl Modifier {
+    /// Whether the modifier would keep rolling forever, because every face of the die meets its condition.
+    pub(crate) fn is_endless(&self, faces: &Faces) -> bool {
+        match self {
+            Modifier::Reroll { once: false, condition } => faces.all_match(condition),
+            Modifier::Explode {
+                condition: Some(condition),
+                ..
+            } => faces.all_match(condition),
+            Modifier::Explode { condition: None, .. } => faces.lowest() == faces.highest(),
+            _ => false,
+        }
+    }
+}
+
+/// Rolls a number of dice, applying the modifiers in a fixed order regardless of the order they were written in:
+/// rerolls, then explosions, then advantage or disadvantage, then keeps and drops, and finally success counting.
+/// Fails once more than `max_dice` dice have been rolled, including any dice rolled by rerolls and explosions.
+/// Returns the number of dice rolled along with the roll, as compounding dice combine several rolls into one die.
+pub(crate) fn roll(
+    rng: &mut dyn DiceRng,
+    count: i64,
+    faces: &Faces,
+    modifiers: &[Modifier],
+    max_dice: u64,
+) -> Result<(Roll, u64), EvaluationError> {
+    if count < 0 {
+        return Err(EvaluationError::InvalidDiceCount(count));
+    }
+
+    let advantage = modifiers.iter().find_map(|modifier| match modifier {
+        Modifier::Advantage => Some(Selection::Highest),
+        Modifier::Disadvantage => Some(Selection::Lowest),
+        _ => None,
+    });
+    let rolled = match advantage {
+        Some(_) => count.checked_mul(2).ok_or(EvaluationError::Overflow)?,
+        None => count,
+    };
+
+    if rolled as u64 > max_dice {
+        return Err(EvaluationError::DiceLimitExceeded(max_dice));
+    }
+
+    let mut roller = Roller {
+        rng,
+        faces,
+        rolled: 0,
+        max_dice,
+    };
+    let mut dice = Vec::new();
+    let mut groups = Vec::new();
+    for _ in 0..rolled {
+        let start = dice.len();
+        roll_die(&mut roller, modifiers, &mut dice)?;
+        groups.push(start..dice.len());
+    }
+
+    if let Some(selection) = advantage {
+        keep(&mut dice, &groups, selection, count);
+    }
+
+    for modifier in modifiers {
+        match modifier {
+            Modifier::Keep(selection, keep_count) => keep(&mut dice, &groups, *selection, modifier_count(*keep_count)?),
+            Modifier::Drop(selection, drop_count) => {
+                let drop_count = modifier_count(*drop_count)?;
+                let counted = groups
+                    .iter()
+                    .filter(|group| is_counted(&dice[group.start..group.end]))
+                    .count() as i64;
+                keep(&mut dice, &groups, opposite(*selection), (counted - drop_count).max(0));
+            }
+            _ => {}
+        }
+    }
+
+    let mut roll = Roll::new(dice);
+
+    for symbol in faces.symbols() {
+        roll.symbols.entry(symbol.clone()).or_insert(0);
+    }
+
+    for modifier in modifiers {
+        let (outcome, condition, default) = match modifier {
+            Modifier::CountSuccesses(condition) => (Outcome::Success, condition, faces.highest()),
+            Modifier::CountFailures(condition) => (Outcome::Failure, condition, faces.lowest()),
+            _ => continue,
+        };
+
+        roll.counts_successes |= outcome == Outcome::Success;
+
+        for die in roll
+            .dice
+            .iter_mut()
+            .filter(|die| die.is_counted() && die.outcome.is_none())
+        {
+            let matches = match condition {
+                Some(condition) => condition.matches(die.value),
+                None if outcome == Outcome::Success => die.value >= default,
+                None => die.value <= default,
+            };
+
+            if matches {
+                die.outcome = Some(outcome);
+            }
+        }
+    }
+
+    Ok((roll, roller.rolled))
+}
+
+/// Rolls dice with the same faces, keeping count of how many have been rolled.
+struct Roller<'r> {
+    rng: &'r mut dyn DiceRng,
+    faces: &'r Faces,
+    rolled: u64,
+    max_dice: u64,
+}
+
+impl Roller<'_> {
+    fn roll(&mut self) -> Result<Die, EvaluationError> {
+        if self.rolled >= self.max_dice {
+            return Err(EvaluationError::DiceLimitExceeded(self.max_dice));
+        }
+
+        self.rolled += 1;
+        Ok(self.faces.roll(self.rng))
+    }
+}
+
+/// Rolls a single die, along with any dice it was rerolled into or exploded into.
+fn roll_die(roller: &mut Roller<'_>, modifiers: &[Modifier], dice: &mut Vec<Die>) -> Result<(), EvaluationError> {
+    let faces = roller.faces;
+    let mut die = roller.roll()?;
+
+    for modifier in modifiers {
+        if let Modifier::Reroll { once, condition } = modifier {
+            while condition.matches(die.value) {
+                die.status = DieStatus::Rerolled;
+                dice.push(die);
+                die = roller.roll()?;
+
+                if *once {
+                    break;
+                }
+            }
+        }
+    }
+
+    let explode = modifiers.iter().find_map(|modifier| match modifier {
+        Modifier::Explode { kind, condition } => Some((*kind, condition)),
+        _ => None,
+    });
+    let (kind, condition) = match explode {
+        Some(explode) => explode,
+        None => {
+            dice.push(die);
+            return Ok(());
+        }
+    };
+    let explodes = |value: i64| match condition {
+        Some(condition) => condition.matches(value),
+        None => value >= faces.highest(),
+    };
+
+    match kind {
+        ExplodeKind::Exploding | ExplodeKind::Penetrating => {
+            let penalty = if kind == ExplodeKind::Penetrating { 1 } else { 0 };
+            let mut rolled = die.value;
+
+            while explodes(rolled) {
+                die.status = DieStatus::Exploded;
+                dice.push(die);
+                die = roller.roll()?;
+                rolled = die.value;
+                die.value -= penalty;
+            }
+
+            dice.push(die);
+        }
+        ExplodeKind::Compounding => {
+            let mut rolled = die.value;
+
+            while explodes(rolled) {
+                let extra = roller.roll()?;
+                rolled = extra.value;
+                die.value = die.value.checked_add(extra.value).ok_or(EvaluationError::Overflow)?;
+                die.status = DieStatus::Exploded;
+
+                for (symbol, count) in extra.symbols {
+                    *die.symbols.entry(symbol).or_default() += count;
+                }
+            }
+
+            dice.push(die);
+        }
+    }
+
+    Ok(())
+}
+
+/// Drops every counted die except for the highest or lowest `count` of them.
+///
+/// Each group holds a die along with the dice it was rerolled or exploded into, which are kept or dropped together.
+fn keep(dice: &mut [Die], groups: &[Range<usize>], selection: Selection, count: i64) {
+    let mut counted: Vec<_> = groups
+        .iter()
+        .filter(|group| is_counted(&dice[group.start..group.end]))
+        .collect();
+    counted.sort_by_key(|group| {
+        dice[group.start..group.end]
+            .iter()
+            .filter(|die| die.is_counted())
+            .fold(0i64, |total, die| total.saturating_add(die.value))
+    });
+
+    if selection == Selection::Highest {
+        counted.reverse();
+    }
+
+    let count = usize::try_from(count).unwrap_or_default();
+    for group in counted.into_iter().skip(count) {
+        for die in dice[group.start..group.end].iter_mut().filter(|die| die.is_counted()) {
+            die.status = DieStatus::Dropped;
+        }
+    }
+}
+
+fn is_counted(group: &[Die]) -> bool {
+    group.iter().any(Die::is_counted)
+}
+
+fn opposite(selection: Selection) -> Selection {
+    match selection {
+        Selection::Highest => Selection::Lowest,
+        Selection::Lowest => Selection::Highest,
+    }
+}
+
+fn modifier_count(count: i64) -> Result<i64, EvaluationError> {
+    if count < 0 {
+        Err(EvaluationError::InvalidModifierCount(count))
+    } else {
+        Ok(count)
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+
+/// The probability of each possible result of a DICE expression, as computed by an [`Analyzer`](super::Analyzer).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    outcomes: BTreeMap<i64, f64>,
+    exact: bool,
+}
+
+impl Distribution {
+    pub(super) fn new(outcomes: BTreeMap<i64, f64>, exact: bool) -> Self {
+        Self { outcomes, exact }
+    }
+
+    /// Whether the probabilities were computed exactly, rather than estimated by rolling the expression many times.
+    pub fn is_exact(&self) -> bool {
+        self.exact
+    }
+
+    /// Each possible result along with its probability, from the lowest result to the highest.
+    pub fn iter(&self) -> impl Iterator<Item = (i64, f64)> + '_ {
+        self.outcomes.iter().map(|(value, probability)| (*value, *probability))
+    }
+
+    pub fn min(&self) -> i64 {
+        self.outcomes.keys().next().copied().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> i64 {
+        self.outcomes.keys().next_back().copied().unwrap_or_default()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.iter().map(|(value, probability)| value as f64 * probability).sum()
+    }
+
+    pub fn variance(&self) -> f64 {
+        let mean = self.mean();
+
+        self.iter()
+            .map(|(value, probability)| (value as f64 - mean).powi(2) * probability)
+            .sum()
+    }
+
+    pub fn standard_deviation(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// The probability of the result being exactly the given value.
+    pub fn probability(&self, value: i64) -> f64 {
+        self.outcomes.get(&value).copied().unwrap_or_default()
+    }
+
+    /// The probability of the result meeting or beating the target, such as an armor class or difficulty.
+    pub fn probability_at_least(&self, target: i64) -> f64 {
+        self.outcomes.range(target..).map(|(_, probability)| probability).sum()
+    }
+
+    pub fn probability_at_most(&self, target: i64) -> f64 {
+        self.outcomes.range(..=target).map(|(_, probability)| probability).sum()
+    }
+}
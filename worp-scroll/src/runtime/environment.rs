@@ -0,0 +1,68 @@
+use super::{EvaluationContext, Transcript, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The names visible to an expression while it's being evaluated.
+///
+/// Identifiers are the pre-defined tables such as `global` and `self`, while variables are those declared with
+/// `$name := {% ... %}` in a macro's variables section. Variables whose values involved dice also keep the transcript
+/// of how they were rolled.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Environment {
+    identifiers: BTreeMap<String, Value>,
+    variables: BTreeMap<String, Value>,
+    #[serde(default)]
+    transcripts: BTreeMap<String, Transcript>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an environment whose pre-defined tables, `global`, `self`, `target` and `targets`, are read from the
+    /// context. `target` is the first of the targets, and it's left undefined when there are no targets, the same as
+    /// `self` when the context has no selected token.
+    pub fn from_context(context: &dyn EvaluationContext) -> Self {
+        let targets = context.target_tables();
+        let mut environment = Self::new().with_identifier("global", context.global_table());
+
+        if let Some(table) = context.self_table() {
+            environment = environment.with_identifier("self", table);
+        }
+
+        if let Some(table) = targets.first() {
+            environment = environment.with_identifier("target", table.clone());
+        }
+
+        environment.with_identifier("targets", Value::List(targets))
+    }
+
+    pub fn with_identifier(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.identifiers.insert(name.into(), value);
+        self
+    }
+
+    #[inline]
+    pub fn identifier(&self, name: &str) -> Option<&Value> {
+        self.identifiers.get(name)
+    }
+
+    #[inline]
+    pub fn variable(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+    pub fn set_variable(&mut self, name: impl Into<String>, value: Value) {
+        self.variables.insert(name.into(), value);
+    }
+
+    #[inline]
+    pub fn transcript(&self, name: &str) -> Option<&Transcript> {
+        self.transcripts.get(name)
+    }
+
+    pub fn set_transcript(&mut self, name: impl Into<String>, transcript: Transcript) {
+        self.transcripts.insert(name.into(), transcript);
+    }
+}
@@ -0,0 +1,1028 @@
+use super::{
+    dice::{self, Condition, Face, Faces, Modifier},
+    function, AttributeChange, DiceRng, Environment, Limits, Step, Table, Transcript, Value,
+};
+use crate::{
+    error::EvaluationError,
+    expression::{BinaryOperator, Expression, RangeOperator, RollCondition, RollModifier, UnaryOperator},
+};
+use std::convert::TryFrom;
+
+/// Evaluates DICE expressions, using the given source of randomness to roll dice.
+pub struct Evaluator<'a> {
+    rng: &'a mut dyn DiceRng,
+    transcript: Transcript,
+    limits: Limits,
+    dice_rolled: u64,
+    steps_taken: u64,
+    changes: Vec<AttributeChange>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(rng: &'a mut dyn DiceRng) -> Self {
+        Self {
+            rng,
+            transcript: Transcript::new(),
+            limits: Limits::default(),
+            dice_rolled: 0,
+            steps_taken: 0,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Limits the dice rolled, steps taken and values built by evaluation, instead of using the default limits.
+    /// The dice and steps are counted across every expression evaluated, until the usage is reset.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Resets the dice rolled and steps taken so far, such as when starting to execute another macro.
+    pub fn reset_usage(&mut self) {
+        self.dice_rolled = 0;
+        self.steps_taken = 0;
+    }
+
+    /// Evaluates an expression down to a single value.
+    ///
+    /// ```
+    /// # use worp_scroll::{Document, SpanKind, runtime::{Environment, Evaluator, Value}};
+    /// # use rand::{rngs::StdRng, SeedableRng as _};
+    /// let document = Document::try_from_str("{% 1d20 + $strength_mod %}")?;
+    /// let expression = match &document.main_macro.body[0].kind {
+    ///     SpanKind::Expression(expression) => expression,
+    ///     _ => unreachable!(),
+    /// };
+    ///
+    /// let mut environment = Environment::new();
+    /// environment.set_variable("strength_mod", Value::Integer(3));
+    ///
+    /// let mut rng = StdRng::seed_from_u64(0);
+    /// let value = Evaluator::new(&mut rng).evaluate(expression, &environment)?.as_integer()?;
+    /// assert!((4..=23).contains(&value));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn evaluate(&mut self, expression: &Expression, environment: &Environment) -> Result<Value, EvaluationError> {
+        self.transcript = Transcript::new();
+        self.evaluate_expression(expression, environment)
+    }
+
+    /// Takes the transcript of the dice rolled by the most recent evaluation.
+    pub fn take_transcript(&mut self) -> Transcript {
+        std::mem::take(&mut self.transcript)
+    }
+
+    /// Takes the changes made to token attributes by assignments, in the order they were made, across every
+    /// expression evaluated since the changes were last taken.
+    pub fn take_changes(&mut self) -> Vec<AttributeChange> {
+        std::mem::take(&mut self.changes)
+    }
+
+    fn evaluate_expression(
+        &mut self,
+        expression: &Expression,
+        environment: &Environment,
+    ) -> Result<Value, EvaluationError> {
+        self.take_steps(1)?;
+        let steps = self.transcript.len();
+        let value = match expression {
+            Expression::Literal(literal) => Value::from(literal),
+            Expression::List(items) => {
+                let values = items
+                    .iter()
+                    .map(|item| self.evaluate_expression(item, environment))
+                    .collect::<Result<_, _>>()?;
+
+                Value::List(values)
+            }
+            Expression::Variable(name) => {
+                let value = environment
+                    .variable(name)
+                    .cloned()
+                    .ok_or_else(|| EvaluationError::UndefinedVariable(name.clone()))?;
+
+                if let Some(transcript) = environment.transcript(name) {
+                    self.transcript.extend(transcript.iter().cloned());
+                }
+
+                value
+            }
+            Expression::Identifier(name) => environment
+                .identifier(name)
+                .cloned()
+                .ok_or_else(|| EvaluationError::UndefinedIdentifier(name.clone()))?,
+            Expression::Field { target, field } => {
+                let target = self.evaluate_expression(target, environment)?;
+                field_of(&target, field)?
+            }
+            Expression::Index { target, index } => {
+                let target = self.evaluate_expression(target, environment)?;
+                let index = self.evaluate_expression(index, environment)?;
+                index_of(&target, &index)?
+            }
+            Expression::Call { function, arguments } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate_expression(argument, environment))
+                    .collect::<Result<_, _>>()?;
+
+                function::call(function, arguments)?
+            }
+            Expression::Unary { operator, operand } => {
+                let operand = self.evaluate_expression(operand, environment)?;
+                unary(*operator, operand)?
+            }
+            Expression::Binary { operator, lhs, rhs } => match operator {
+                BinaryOperator::And => {
+                    let lhs = self.evaluate_expression(lhs, environment)?.as_boolean()?;
+                    Value::Boolean(lhs && self.evaluate_expression(rhs, environment)?.as_boolean()?)
+                }
+                BinaryOperator::Or => {
+                    let lhs = self.evaluate_expression(lhs, environment)?.as_boolean()?;
+                    Value::Boolean(lhs || self.evaluate_expression(rhs, environment)?.as_boolean()?)
+                }
+                _ => {
+                    let lhs = self.evaluate_expression(lhs, environment)?;
+                    let rhs = self.evaluate_expression(rhs, environment)?;
+                    self.limit_size(binary(*operator, lhs, rhs)?)?
+                }
+            },
+            Expression::Range { operator, start, end } => {
+                let start = self.evaluate_expression(start, environment)?.as_integer()?;
+                let end = self.evaluate_expression(end, environment)?.as_integer()?;
+                self.take_steps(end.saturating_sub(start).max(0) as u64)?;
+                range(*operator, start, end)?
+            }
+            Expression::Roll {
+                count,
+                sides,
+                modifiers,
+            } => {
+                let count = self.evaluate_expression(count, environment)?.as_integer()?;
+                let faces = self.evaluate_faces(sides, environment)?;
+                let mut evaluated_modifiers = Vec::with_capacity(modifiers.len());
+
+                for modifier in modifiers {
+                    let evaluated_modifier = self.evaluate_modifier(modifier, environment)?;
+
+                    if evaluated_modifier.is_endless(&faces) {
+                        return Err(EvaluationError::EndlessModifier(modifier.to_string()));
+                    }
+
+                    evaluated_modifiers.push(evaluated_modifier);
+                }
+
+                let remaining_dice = self.limits.max_total_dice.saturating_sub(self.dice_rolled);
+                let max_dice = self.limits.max_dice_per_roll.min(remaining_dice);
+                let (roll, rolled) = dice::roll(self.rng, count, &faces, &evaluated_modifiers, max_dice).map_err(
+                    |error| match error {
+                        EvaluationError::DiceLimitExceeded(_) if remaining_dice < self.limits.max_dice_per_roll => {
+                            EvaluationError::TotalDiceLimitExceeded(self.limits.max_total_dice)
+                        }
+                        error => error,
+                    },
+                )?;
+                self.dice_rolled += rolled;
+
+                Value::Roll(roll)
+            }
+            Expression::Assign {
+                operator,
+                target,
+                value,
+            } => {
+                let (table, attribute) = match &**target {
+                    Expression::Field { target, field } => (self.evaluate_expression(target, environment)?, field),
+                    _ => return Err(EvaluationError::InvalidAssignment(target.to_string())),
+                };
+                let token = match field_of(&table, "id") {
+                    Ok(Value::String(id)) => id,
+                    _ => return Err(EvaluationError::InvalidAssignment(target.to_string())),
+                };
+                let previous = self
+                    .changes
+                    .iter()
+                    .rev()
+                    .find(|change| change.token == token && change.attribute == *attribute)
+                    .map(|change| change.value.clone())
+                    .or_else(|| field_of(&table, attribute).ok());
+
+                let value = self.evaluate_expression(value, environment)?;
+                let value = match operator.binary_operator() {
+                    Some(operator) => {
+                        let previous = previous
+                            .clone()
+                            .ok_or_else(|| EvaluationError::KeyNotFound(attribute.clone()))?;
+                        self.limit_size(binary(operator, previous, value)?)?
+                    }
+                    None => value,
+                };
+                let value = match value {
+                    Value::Roll(roll) => Value::Integer(roll.total()?),
+                    value => value,
+                };
+                // Attributes only keep numbers.
+                value.as_decimal()?;
+
+                self.changes.push(AttributeChange {
+                    token,
+                    attribute: attribute.clone(),
+                    previous,
+                    value: value.clone(),
+                });
+
+                value
+            }
+        };
+
+        self.record(expression, steps, &value);
+
+        Ok(value)
+    }
+
+    fn take_steps(&mut self, steps: u64) -> Result<(), EvaluationError> {
+        self.steps_taken = self.steps_taken.saturating_add(steps);
+
+        if self.steps_taken > self.limits.max_steps {
+            return Err(EvaluationError::StepLimitExceeded(self.limits.max_steps));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a string built by an operator, which can double in size with each addition, stays within limits.
+    fn limit_size(&self, value: Value) -> Result<Value, EvaluationError> {
+        match &value {
+            Value::String(string) if string.len() > self.limits.max_value_size => {
+                Err(EvaluationError::ValueSizeLimitExceeded(self.limits.max_value_size))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// Records the dice rolled by an expression, or its result if dice were rolled while evaluating it.
+    fn record(&mut self, expression: &Expression, steps: usize, value: &Value) {
+        match (expression, value) {
+            (Expression::Roll { .. }, Value::Roll(roll)) => self.transcript.push(Step::Roll {
+                expression: expression.to_string(),
+                roll: roll.clone(),
+            }),
+            (Expression::Variable(_), _) => {}
+            _ if self.transcript.len() > steps => self.transcript.push(Step::Total {
+                expression: expression.to_string(),
+                value: value.clone(),
+            }),
+            _ => {}
+        }
+    }
+
+    /// Evaluates the sides of a roll into the faces of its dice, looking up names that aren't identifiers as named dice.
+    pub(super) fn evaluate_faces(
+        &mut self,
+        sides: &Expression,
+        environment: &Environment,
+    ) -> Result<Faces, EvaluationError> {
+        match sides {
+            Expression::Identifier(name) if environment.identifier(name).is_none() => named_die(name, environment),
+            sides => faces(self.evaluate_expression(sides, environment)?),
+        }
+    }
+
+    pub(super) fn evaluate_modifier(
+        &mut self,
+        modifier: &RollModifier,
+        environment: &Environment,
+    ) -> Result<Modifier, EvaluationError> {
+        let modifier = match modifier {
+            RollModifier::Keep { selection, count } => {
+                Modifier::Keep(*selection, self.evaluate_count(count, environment)?)
+            }
+            RollModifier::Drop { selection, count } => {
+                Modifier::Drop(*selection, self.evaluate_count(count, environment)?)
+            }
+            RollModifier::Advantage => Modifier::Advantage,
+            RollModifier::Disadvantage => Modifier::Disadvantage,
+            RollModifier::Reroll { once, condition } => Modifier::Reroll {
+                once: *once,
+                condition: self.evaluate_condition(condition, environment)?,
+            },
+            RollModifier::Explode { kind, condition } => Modifier::Explode {
+                kind: *kind,
+                condition: self.evaluate_optional_condition(condition, environment)?,
+            },
+            RollModifier::CountSuccesses { condition } => {
+                Modifier::CountSuccesses(self.evaluate_optional_condition(condition, environment)?)
+            }
+            RollModifier::CountFailures { condition } => {
+                Modifier::CountFailures(self.evaluate_optional_condition(condition, environment)?)
+            }
+        };
+
+        Ok(modifier)
+    }
+
+    fn evaluate_count(
+        &mut self,
+        count: &Option<Expression>,
+        environment: &Environment,
+    ) -> Result<i64, EvaluationError> {
+        match count {
+            Some(count) => self.evaluate_expression(count, environment)?.as_integer(),
+            None => Ok(1),
+        }
+    }
+
+    fn evaluate_optional_condition(
+        &mut self,
+        condition: &Option<RollCondition>,
+        environment: &Environment,
+    ) -> Result<Option<Condition>, EvaluationError> {
+        condition
+            .as_ref()
+            .map(|condition| self.evaluate_condition(condition, environment))
+            .transpose()
+    }
+
+    fn evaluate_condition(
+        &mut self,
+        condition: &RollCondition,
+        environment: &Environment,
+    ) -> Result<Condition, EvaluationError> {
+        Ok(Condition {
+            operator: condition.operator,
+            value: self.evaluate_expression(&condition.value, environment)?.as_integer()?,
+        })
+    }
+}
+
+/// Converts the sides of a roll into the faces of its dice.
+fn faces(sides: Value) -> Result<Faces, EvaluationError> {
+    match sides {
+        Value::List(_) => {
+            let faces = list_faces(&sides)?;
+
+            if faces.is_empty() {
+                return Err(EvaluationError::EmptyList);
+            }
+
+            Ok(Faces::List(faces))
+        }
+        sides => {
+            let sides = sides.as_integer()?;
+
+            if sides < 1 {
+                return Err(EvaluationError::InvalidDiceSides(sides));
+            }
+
+            Ok(Faces::Sides(sides))
+        }
+    }
+}
+
+/// Looks up a die registered by name in the game's `global.dice` table, such as `1dBoost`.
+/// The Fate die `dF` is built in, but a game can still register its own die with that name.
+fn named_die(name: &str, environment: &Environment) -> Result<Faces, EvaluationError> {
+    let registered = match environment.identifier("global") {
+        Some(Value::Table(Table::StringKeyed(global))) => match global.get("dice") {
+            Some(Value::Table(Table::StringKeyed(dice))) => dice.get(name),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match registered {
+        Some(sides) => faces(sides.clone()),
+        None if name == "F" => Ok(Faces::fate()),
+        None => Err(EvaluationError::UndefinedDie(name.to_owned())),
+    }
+}
+
+/// Flattens a list, or nested lists, of faces into the faces of a die.
+/// Each face is either an integer or a table counting the symbols it shows, such as `{"success": 1, "advantage": 1}`.
+fn list_faces(value: &Value) -> Result<Vec<Face>, EvaluationError> {
+    match value {
+        Value::List(values) => {
+            let mut faces = Vec::new();
+
+            for value in values {
+                faces.extend(list_faces(value)?);
+            }
+
+            Ok(faces)
+        }
+        Value::Table(Table::StringKeyed(entries)) => {
+            let mut face = Face::number(0);
+
+            for (symbol, count) in entries {
+                face.symbols.insert(symbol.clone(), count.as_integer()?);
+            }
+
+            Ok(vec![face])
+        }
+        value => Ok(vec![Face::number(value.as_integer()?)]),
+    }
+}
+
+fn field_of(target: &Value, field: &str) -> Result<Value, EvaluationError> {
+    match target {
+        Value::Table(Table::StringKeyed(entries)) => entries
+            .get(field)
+            .cloned()
+            .ok_or_else(|| EvaluationError::KeyNotFound(field.to_owned())),
+        Value::Roll(roll) => match field {
+            "total" => roll.total().map(Value::Integer),
+            "successes" => Ok(Value::Integer(roll.successes())),
+            "failures" => Ok(Value::Integer(roll.failures())),
+            "dice" => Ok(Value::List(roll.values().map(Value::Integer).collect())),
+            "symbols" => Ok(Value::Table(Table::StringKeyed(
+                roll.symbols
+                    .iter()
+                    .map(|(symbol, count)| (symbol.clone(), Value::Integer(*count)))
+                    .collect(),
+            ))),
+            _ => Err(EvaluationError::KeyNotFound(field.to_owned())),
+        },
+        _ => Err(target.type_mismatch("table")),
+    }
+}
+
+fn index_of(target: &Value, index: &Value) -> Result<Value, EvaluationError> {
+    match (target, index) {
+        (Value::Table(Table::StringKeyed(entries)), Value::String(key)) => entries
+            .get(key)
+            .cloned()
+            .ok_or_else(|| EvaluationError::KeyNotFound(key.clone())),
+        (Value::Table(Table::IntegerKeyed(entries)), index) => {
+            let key = index.as_integer()?;
+
+            entries
+                .get(&key)
+                .cloned()
+                .ok_or_else(|| EvaluationError::KeyNotFound(key.to_string()))
+        }
+        (Value::Table(Table::StringKeyed(_)), index) => Err(index.type_mismatch("string")),
+        (Value::List(values), index) => {
+            let index = index.as_integer()?;
+            let value = usize::try_from(index).ok().and_then(|index| values.get(index));
+
+            value.cloned().ok_or(EvaluationError::IndexOutOfBounds(index))
+        }
+        (Value::Roll(roll), index) => {
+            let index = index.as_integer()?;
+            let die = usize::try_from(index).ok().and_then(|index| roll.values().nth(index));
+
+            die.map(Value::Integer).ok_or(EvaluationError::IndexOutOfBounds(index))
+        }
+        (target, _) => Err(target.type_mismatch("table or list")),
+    }
+}
+
+pub(super) fn unary(operator: UnaryOperator, operand: Value) -> Result<Value, EvaluationError> {
+    match operator {
+        UnaryOperator::Not => Ok(Value::Boolean(!operand.as_boolean()?)),
+        UnaryOperator::Negate => match operand {
+            Value::Decimal(value) => Ok(Value::Decimal(-value)),
+            operand => operand
+                .as_integer()?
+                .checked_neg()
+                .map(Value::Integer)
+                .ok_or(EvaluationError::Overflow),
+        },
+    }
+}
+
+pub(super) fn binary(operator: BinaryOperator, lhs: Value, rhs: Value) -> Result<Value, EvaluationError> {
+    let result = match operator {
+        BinaryOperator::Equal => Value::Boolean(equals(&lhs, &rhs)),
+        BinaryOperator::NotEqual => Value::Boolean(!equals(&lhs, &rhs)),
+        BinaryOperator::LessThan
+        | BinaryOperator::LessThanOrEqual
+        | BinaryOperator::GreaterThan
+        | BinaryOperator::GreaterThanOrEqual => {
+            let ordering = match (&lhs, &rhs) {
+                (Value::String(lhs), Value::String(rhs)) => Some(lhs.cmp(rhs)),
+                _ => Number::try_from(&lhs)?.partial_cmp(&Number::try_from(&rhs)?),
+            };
+            let result = match ordering {
+                Some(ordering) => match operator {
+                    BinaryOperator::LessThan => ordering.is_lt(),
+                    BinaryOperator::LessThanOrEqual => ordering.is_le(),
+                    BinaryOperator::GreaterThan => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                },
+                None => false,
+            };
+
+            Value::Boolean(result)
+        }
+        BinaryOperator::Add => match (&lhs, &rhs) {
+            (Value::String(lhs), rhs) => Value::String(format!("{}{}", lhs, rhs)),
+            _ => arithmetic(operator, &lhs, &rhs)?,
+        },
+        BinaryOperator::Subtract | BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => {
+            arithmetic(operator, &lhs, &rhs)?
+        }
+        BinaryOperator::And | BinaryOperator::Or => Value::Boolean(lhs.as_boolean()? && rhs.as_boolean()?),
+    };
+
+    Ok(result)
+}
+
+fn arithmetic(operator: BinaryOperator, lhs: &Value, rhs: &Value) -> Result<Value, EvaluationError> {
+    let result = match (Number::try_from(lhs)?, Number::try_from(rhs)?) {
+        (Number::Integer(lhs), Number::Integer(rhs)) => {
+            let result = match operator {
+                BinaryOperator::Add => lhs.checked_add(rhs),
+                BinaryOperator::Subtract => lhs.checked_sub(rhs),
+                BinaryOperator::Multiply => lhs.checked_mul(rhs),
+                BinaryOperator::Divide | BinaryOperator::Remainder if rhs == 0 => {
+                    return Err(EvaluationError::DivisionByZero)
+                }
+                BinaryOperator::Divide => lhs.checked_div(rhs),
+                _ => lhs.checked_rem(rhs),
+            };
+
+            Value::Integer(result.ok_or(EvaluationError::Overflow)?)
+        }
+        (lhs, rhs) => {
+            let (lhs, rhs) = (lhs.as_decimal(), rhs.as_decimal());
+            let result = match operator {
+                BinaryOperator::Add => lhs + rhs,
+                BinaryOperator::Subtract => lhs - rhs,
+                BinaryOperator::Multiply => lhs * rhs,
+                BinaryOperator::Divide | BinaryOperator::Remainder if rhs == 0.0 => {
+                    return Err(EvaluationError::DivisionByZero)
+                }
+                BinaryOperator::Divide => lhs / rhs,
+                _ => lhs % rhs,
+            };
+
+            Value::Decimal(result)
+        }
+    };
+
+    Ok(result)
+}
+
+fn equals(lhs: &Value, rhs: &Value) -> bool {
+    match (Number::try_from(lhs), Number::try_from(rhs)) {
+        (Ok(lhs), Ok(rhs)) => lhs == rhs,
+        _ => lhs == rhs,
+    }
+}
+
+/// Produces the list of integers in a range, which must have its start before its end and contain at least one value.
+/// Ranges with both bounds included may start and end on the same value, such as `2=..=2`.
+fn range(operator: RangeOperator, start: i64, end: i64) -> Result<Value, EvaluationError> {
+    if start > end {
+        return Err(EvaluationError::InvertedRange { operator, start, end });
+    }
+
+    let (first, last) = match operator {
+        RangeOperator::Exclusive => (start.saturating_add(1), end.saturating_sub(1)),
+        RangeOperator::InclusiveStart => (start, end.saturating_sub(1)),
+        RangeOperator::InclusiveEnd => (start.saturating_add(1), end),
+        RangeOperator::Inclusive => (start, end),
+    };
+
+    if first > last {
+        return Err(EvaluationError::EmptyRange { operator, start, end });
+    }
+
+    Ok(Value::List((first..=last).map(Value::Integer).collect()))
+}
+
+/// A numeric view of a value, used to mix integers, decimals and rolls in arithmetic.
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Integer(i64),
+    Decimal(f64),
+}
+
+impl Number {
+    fn as_decimal(self) -> f64 {
+        match self {
+            Number::Integer(value) => value as f64,
+            Number::Decimal(value) => value,
+        }
+    }
+}
+
+impl TryFrom<&Value> for Number {
+    type Error = EvaluationError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Decimal(value) => Ok(Number::Decimal(*value)),
+            Value::Integer(_) | Value::Roll(_) => Ok(Number::Integer(value.as_integer()?)),
+            _ => Err(value.type_mismatch("number")),
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Number::Integer(lhs), Number::Integer(rhs)) => lhs == rhs,
+            (lhs, rhs) => lhs.as_decimal() == rhs.as_decimal(),
+        }
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Number::Integer(lhs), Number::Integer(rhs)) => lhs.partial_cmp(rhs),
+            (lhs, rhs) => lhs.as_decimal().partial_cmp(&rhs.as_decimal()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        runtime::{Die, DieStatus, Roll},
+        Document, Literal, SpanKind,
+    };
+    use rand::{rngs::StdRng, SeedableRng as _};
+    use std::collections::BTreeMap;
+
+    fn evaluate(input: &str, environment: &Environment) -> Result<Value, EvaluationError> {
+        let document = Document::try_from_str(&format!("{{% {} %}}", input)).unwrap();
+        let expression = match &document.main_macro.body[0].kind {
+            SpanKind::Expression(expression) => expression,
+            _ => unreachable!(),
+        };
+
+        let mut rng = StdRng::seed_from_u64(0);
+        Evaluator::new(&mut rng).evaluate(expression, environment)
+    }
+
+    #[test]
+    fn evaluate_arithmetic() {
+        let environment = Environment::new();
+
+        assert_eq!(evaluate("1 + 2 * 3", &environment), Ok(Value::Integer(7)));
+        assert_eq!(evaluate("(1 + 2) * 3", &environment), Ok(Value::Integer(9)));
+        assert_eq!(evaluate("7 / 2", &environment), Ok(Value::Integer(3)));
+        assert_eq!(evaluate("7 / 2.0", &environment), Ok(Value::Decimal(3.5)));
+        assert_eq!(evaluate("-3 % 2", &environment), Ok(Value::Integer(-1)));
+        assert_eq!(evaluate("1 / 0", &environment), Err(EvaluationError::DivisionByZero));
+    }
+
+    #[test]
+    fn evaluate_logic_and_comparisons() {
+        let environment = Environment::new();
+
+        assert_eq!(evaluate("1 < 2 && 2.5 >= 2", &environment), Ok(Value::Boolean(true)));
+        assert_eq!(
+            evaluate("!(1 == 1.0) || \"a\" != \"a\"", &environment),
+            Ok(Value::Boolean(false))
+        );
+        assert_eq!(evaluate("false && 1", &environment), Ok(Value::Boolean(false)));
+        assert!(matches!(
+            evaluate("1 && true", &environment),
+            Err(EvaluationError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn evaluate_rolls_with_seeded_rng_is_reproducible() {
+        let mut environment = Environment::new();
+        environment.set_variable("strength_mod", Value::Integer(3));
+
+        let first = evaluate("4d6 + $strength_mod", &environment).unwrap();
+        let second = evaluate("4d6 + $strength_mod", &environment).unwrap();
+        let total = first.as_integer().unwrap();
+
+        assert_eq!(first, second);
+        assert!((7..=27).contains(&total));
+    }
+
+    #[test]
+    fn evaluate_rolls_from_lists() {
+        let environment = Environment::new();
+
+        for _ in 0..10 {
+            let value = evaluate("1d[1, [3, 5], 2=..=2]", &environment)
+                .unwrap()
+                .as_integer()
+                .unwrap();
+            assert!([1, 2, 3, 5].contains(&value));
+        }
+
+        assert_eq!(evaluate("0d6", &environment), Ok(Value::Roll(Roll::new(vec![]))));
+        assert_eq!(evaluate("1d0", &environment), Err(EvaluationError::InvalidDiceSides(0)));
+        assert_eq!(evaluate("1d[]", &environment), Err(EvaluationError::EmptyList));
+    }
+
+    #[test]
+    fn evaluate_table_access() {
+        let ability_mods = (1..=20).map(|score| (score, Value::Integer(score / 2 - 5))).collect();
+        let mut token = BTreeMap::new();
+        token.insert("strength".to_owned(), Value::Integer(16));
+        token.insert("name".to_owned(), Value::String("Gimli".to_owned()));
+        let mut global = BTreeMap::new();
+        global.insert(
+            "ability_mods".to_owned(),
+            Value::Table(Table::IntegerKeyed(ability_mods)),
+        );
+
+        let environment = Environment::new()
+            .with_identifier("self", Value::Table(Table::StringKeyed(token)))
+            .with_identifier("global", Value::Table(Table::StringKeyed(global)));
+
+        assert_eq!(
+            evaluate("global.ability_mods[self.strength]", &environment),
+            Ok(Value::Integer(3))
+        );
+        assert_eq!(
+            evaluate("self[\"name\"] + \" attacks\"", &environment),
+            Ok(Value::String("Gimli attacks".to_owned()))
+        );
+        assert_eq!(
+            evaluate("self.dexterity", &environment),
+            Err(EvaluationError::KeyNotFound("dexterity".to_owned()))
+        );
+        assert_eq!(
+            evaluate("$missing", &environment),
+            Err(EvaluationError::UndefinedVariable("missing".to_owned()))
+        );
+    }
+
+    #[test]
+    fn evaluate_functions() {
+        let environment = Environment::new();
+
+        assert_eq!(evaluate("sum([1, 2, 3])", &environment), Ok(Value::Integer(6)));
+        assert_eq!(evaluate("max(1, 5, 3)", &environment), Ok(Value::Integer(5)));
+        assert_eq!(evaluate("len(3d6)", &environment), Ok(Value::Integer(3)));
+        assert_eq!(evaluate("floor(7 / 2.0)", &environment), Ok(Value::Integer(3)));
+        assert!(matches!(
+            evaluate("nope(1)", &environment),
+            Err(EvaluationError::UndefinedFunction(_))
+        ));
+    }
+
+    /// Rolls the given values in order, regardless of the sides of the dice.
+    struct Sequence<'a>(std::slice::Iter<'a, u64>);
+
+    impl DiceRng for Sequence<'_> {
+        fn roll_die(&mut self, _sides: u64) -> u64 {
+            *self.0.next().unwrap()
+        }
+    }
+
+    #[test]
+    fn evaluate_records_transcript() {
+        let document = Document::try_from_str("{% 2d8 + 2 + $strength_mod %}").unwrap();
+        let expression = match &document.main_macro.body[0].kind {
+            SpanKind::Expression(expression) => expression,
+            _ => unreachable!(),
+        };
+        let mut environment = Environment::new();
+        environment.set_variable("strength_mod", Value::Integer(3));
+
+        let mut rng = Sequence([5, 1].iter());
+        let mut evaluator = Evaluator::new(&mut rng);
+        assert_eq!(evaluator.evaluate(expression, &environment), Ok(Value::Integer(11)));
+        assert_eq!(
+            evaluator.take_transcript().to_string(),
+            "2d8: [5, 1] = 6\n2d8 + 2 = 8\n2d8 + 2 + $strength_mod = 11"
+        );
+
+        environment.set_variable("strength_mod", Value::Integer(8));
+        let mut transcript = Transcript::new();
+        transcript.push(Step::Roll {
+            expression: "1d20".to_owned(),
+            roll: Roll::new(vec![Die::kept(8)]),
+        });
+        environment.set_transcript("strength_mod", transcript);
+        let mut rng = Sequence([2, 3].iter());
+        let mut evaluator = Evaluator::new(&mut rng);
+        evaluator.evaluate(expression, &environment).unwrap();
+        assert_eq!(evaluator.take_transcript().len(), 4);
+
+        let mut rng = Sequence([].iter());
+        let mut evaluator = Evaluator::new(&mut rng);
+        evaluator
+            .evaluate(&Expression::Literal(Literal::Integer(1)), &environment)
+            .unwrap();
+        assert!(evaluator.take_transcript().is_empty());
+    }
+
+    #[test]
+    fn evaluate_counts_compounded_dice_towards_limits() {
+        let document = Document::try_from_str("{% 1d6!! + 1d6!! %}").unwrap();
+        let expression = match &document.main_macro.body[0].kind {
+            SpanKind::Expression(expression) => expression,
+            _ => unreachable!(),
+        };
+        let limits = Limits {
+            max_total_dice: 4,
+            ..Limits::default()
+        };
+
+        // The first die compounds into a single die, but three dice were rolled for it.
+        let mut rng = Sequence([6, 6, 1, 6, 1].iter());
+        assert_eq!(
+            Evaluator::new(&mut rng)
+                .with_limits(limits)
+                .evaluate(expression, &Environment::new()),
+            Err(EvaluationError::TotalDiceLimitExceeded(4))
+        );
+    }
+
+    fn roll(input: &str, rolls: &[u64]) -> Result<Roll, EvaluationError> {
+        let document = Document::try_from_str(&format!("{{% {} %}}", input)).unwrap();
+        let expression = match &document.main_macro.body[0].kind {
+            SpanKind::Expression(expression) => expression,
+            _ => unreachable!(),
+        };
+
+        let mut rng = Sequence(rolls.iter());
+        match Evaluator::new(&mut rng).evaluate(expression, &Environment::new())? {
+            Value::Roll(roll) => Ok(roll),
+            value => panic!("expected a roll, found {:?}", value),
+        }
+    }
+
+    #[test]
+    fn evaluate_roll_modifiers() {
+        let totals = &[
+            ("4d6kh3", vec![3, 6, 1, 5], 14),
+            ("4d6dl", vec![3, 6, 1, 5], 14),
+            ("4d6dh1dl1", vec![3, 6, 1, 5], 8),
+            ("1d20adv", vec![4, 17], 17),
+            ("1d20dis", vec![4, 17], 4),
+            ("2d6r1", vec![1, 1, 4, 2], 6),
+            ("1d6ro1", vec![1, 1], 1),
+            ("1d6!", vec![6, 6, 2], 14),
+            ("1d6!>4", vec![5, 3], 8),
+            ("1d6!!", vec![6, 3], 9),
+            ("1d6!p", vec![6, 6, 3], 13),
+            ("2d6!kh1", vec![6, 3, 5], 9),
+            ("2d6!dh1", vec![6, 3, 5], 5),
+            ("1d6!adv", vec![6, 6, 1, 5], 13),
+            ("2d6r1kl1", vec![1, 6, 4], 4),
+        ];
+
+        for (input, rolls, total) in totals {
+            assert_eq!(roll(input, rolls).unwrap().total(), Ok(*total), "{}", input);
+        }
+
+        let statuses: Vec<_> = roll("2d6r1!", &[1, 6, 2, 3])
+            .unwrap()
+            .dice
+            .iter()
+            .map(|die| die.status)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                DieStatus::Rerolled,
+                DieStatus::Exploded,
+                DieStatus::Kept,
+                DieStatus::Kept
+            ]
+        );
+        assert_eq!(
+            roll("1d6r<7", &[]),
+            Err(EvaluationError::EndlessModifier("r<7".to_owned()))
+        );
+        assert_eq!(roll("1d1!", &[]), Err(EvaluationError::EndlessModifier("!".to_owned())));
+        assert_eq!(
+            roll("4d6kh(-1)", &[1, 2, 3, 4]),
+            Err(EvaluationError::InvalidModifierCount(-1))
+        );
+    }
+
+    #[test]
+    fn evaluate_roll_modifiers_in_transcript() {
+        let document = Document::try_from_str("{% 4d6kh3 %}").unwrap();
+        let expression = match &document.main_macro.body[0].kind {
+            SpanKind::Expression(expression) => expression,
+            _ => unreachable!(),
+        };
+
+        let mut rng = Sequence([3, 6, 1, 5].iter());
+        let mut evaluator = Evaluator::new(&mut rng);
+        let value = evaluator.evaluate(expression, &Environment::new()).unwrap();
+
+        assert_eq!(index_of(&value, &Value::Integer(2)), Ok(Value::Integer(5)));
+        assert_eq!(evaluator.take_transcript().to_string(), "4d6kh3: [3, 6, (1), 5] = 14");
+    }
+
+    #[test]
+    fn evaluate_dice_pools() {
+        let pool = roll("5d10cs>=8cf", &[9, 3, 1, 8, 10]).unwrap();
+        assert_eq!((pool.successes(), pool.failures(), pool.total()), (3, 1, Ok(3)));
+
+        let pool = roll("4d6cf1", &[1, 3, 1, 6]).unwrap();
+        assert_eq!((pool.successes(), pool.failures(), pool.total()), (0, 2, Ok(11)));
+
+        let pool = roll("3d10!cs", &[10, 4, 2, 10, 1]).unwrap();
+        assert_eq!(pool.total(), Ok(2));
+
+        let pool = roll("2d6!!cs", &[6, 3, 4]).unwrap();
+        assert_eq!((pool.successes(), pool.total()), (1, Ok(1)));
+
+        let document = Document::try_from_str("{% (5d10cs>=8cf).successes * 10 + $pool.failures %}").unwrap();
+        let expression = match &document.main_macro.body[0].kind {
+            SpanKind::Expression(expression) => expression,
+            _ => unreachable!(),
+        };
+        let mut environment = Environment::new();
+        environment.set_variable("pool", Value::Roll(roll("2d6cf", &[1, 1]).unwrap()));
+        let mut rng = Sequence([9, 3, 1, 8, 10].iter());
+        let mut evaluator = Evaluator::new(&mut rng);
+
+        assert_eq!(evaluator.evaluate(expression, &environment), Ok(Value::Integer(32)));
+        assert_eq!(
+            evaluator.take_transcript()[0].to_string(),
+            "5d10cs>=8cf: [9s, 3, 1f, 8s, 10s] = 3"
+        );
+    }
+
+    #[test]
+    fn evaluate_fate_percentile_and_named_dice() {
+        assert_eq!(roll("4dF", &[1, 2, 3, 3]).unwrap().total(), Ok(1));
+        assert_eq!(roll("1d%", &[42]).unwrap().total(), Ok(42));
+        assert_eq!(roll("1dX", &[1]), Err(EvaluationError::UndefinedDie("X".to_owned())));
+
+        let face = |symbols: &[(&str, i64)]| {
+            Value::Table(Table::StringKeyed(
+                symbols
+                    .iter()
+                    .map(|(symbol, count)| (symbol.to_string(), Value::Integer(*count)))
+                    .collect(),
+            ))
+        };
+        let boost = Value::List(vec![
+            face(&[]),
+            face(&[("success", 1)]),
+            face(&[("success", 1), ("advantage", 1)]),
+            face(&[("advantage", 2)]),
+        ]);
+        let mut dice = BTreeMap::new();
+        dice.insert("Boost".to_owned(), boost);
+        let mut global = BTreeMap::new();
+        global.insert("dice".to_owned(), Value::Table(Table::StringKeyed(dice)));
+        let environment = Environment::new().with_identifier("global", Value::Table(Table::StringKeyed(global)));
+
+        let document = Document::try_from_str("{% 2dBoost %}{% 1dBoost %}{% (2dBoost).symbols.advantage %}").unwrap();
+        let mut rng = Sequence([3, 4, 1, 4, 4].iter());
+        let mut evaluator = Evaluator::new(&mut rng);
+        let results: Vec<_> = document
+            .main_macro
+            .body
+            .iter()
+            .map(|span| match &span.kind {
+                SpanKind::Expression(expression) => evaluator.evaluate(expression, &environment).unwrap(),
+                _ => unreachable!(),
+            })
+            .collect();
+
+        assert_eq!(results[0].to_string(), "advantage: 3, success: 1");
+        assert_eq!(results[1].to_string(), "advantage: 0, success: 0");
+        assert_eq!(results[2], Value::Integer(4));
+        assert_eq!(
+            evaluator.take_transcript()[0].to_string(),
+            "2dBoost: [advantage advantage, advantage advantage] = advantage: 4, success: 0"
+        );
+    }
+
+    #[test]
+    fn evaluate_ranges() {
+        let environment = Environment::new();
+        let list = |values: &[i64]| Value::List(values.iter().copied().map(Value::Integer).collect());
+
+        assert_eq!(evaluate("1..4", &environment), Ok(list(&[2, 3])));
+        assert_eq!(evaluate("1=..4", &environment), Ok(list(&[1, 2, 3])));
+        assert_eq!(evaluate("1..=4", &environment), Ok(list(&[2, 3, 4])));
+        assert_eq!(evaluate("1=..=4", &environment), Ok(list(&[1, 2, 3, 4])));
+        assert_eq!(evaluate("-2=..=-2", &environment), Ok(list(&[-2])));
+
+        assert_eq!(
+            evaluate("6=..=2", &environment),
+            Err(EvaluationError::InvertedRange {
+                operator: RangeOperator::Inclusive,
+                start: 6,
+                end: 2,
+            })
+        );
+        assert_eq!(
+            evaluate("1d(3..4)", &environment),
+            Err(EvaluationError::EmptyRange {
+                operator: RangeOperator::Exclusive,
+                start: 3,
+                end: 4,
+            })
+        );
+        assert_eq!(
+            evaluate("2..=2", &environment).unwrap_err().to_string(),
+            "Range `2..=2` is empty, as it excludes every value between its bounds."
+        );
+    }
+}
@@ -0,0 +1,1310 @@
+use super::{
+    AttributeChange, Continuation, DiceRng, Environment, Evaluator, Fragment, InputField, InputRequest, Limits,
+    MacroLibrary, Output, Value,
+};
+use crate::{
+    error::{EvaluationError, ExecutionError},
+    Definition, Document, Literal, Prompt, PromptType, Span, SpanKind, Symbol, VariableKind, VariableList,
+};
+use std::collections::BTreeMap;
+
+/// Executes macro documents, producing their formatted output.
+pub struct Executor<'a> {
+    evaluator: Evaluator<'a>,
+    environment: Environment,
+    library: Option<&'a dyn MacroLibrary>,
+    call_stack: Vec<MacroCall>,
+    limits: Limits,
+    output_size: usize,
+    /// The answers given with [`Executor::answer`], waiting for the next execution.
+    pending_answers: BTreeMap<String, Value>,
+    /// The answers used by the current execution.
+    answers: BTreeMap<String, Value>,
+}
+
+/// A macro which is currently being executed, used to detect recursive macro calls.
+/// Macros are identified by the name of their document in the library, if any, and the name of the sub-macro.
+#[derive(PartialEq)]
+struct MacroCall {
+    document: Option<String>,
+    sub_macro: Option<String>,
+}
+
+/// The document that the macro being executed belongs to, along with the variables declared by its main macro.
+struct Scope<'d> {
+    document: &'d Document,
+    /// The name of the document in the library, or `None` for the document being executed.
+    name: Option<String>,
+    environment: Environment,
+}
+
+impl MacroCall {
+    /// The name the macro is embedded with, such as `damage` for `#damage`.
+    fn name(&self) -> &str {
+        self.sub_macro
+            .as_deref()
+            .or(self.document.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+impl<'a> Executor<'a> {
+    /// Creates an executor that rolls dice with the given source of randomness and
+    /// resolves pre-defined tables, such as `global` and `self`, from the given environment.
+    pub fn new(rng: &'a mut dyn DiceRng, environment: Environment) -> Self {
+        Self {
+            evaluator: Evaluator::new(rng),
+            environment,
+            library: None,
+            call_stack: Vec::new(),
+            limits: Limits::default(),
+            output_size: 0,
+            pending_answers: BTreeMap::new(),
+            answers: BTreeMap::new(),
+        }
+    }
+
+    /// Limits the work done by each execution, instead of using the default limits.
+    ///
+    /// ```
+    /// # use worp_scroll::{Document, error::{EvaluationError, ExecutionError}, runtime::{Environment, Executor, Limits}};
+    /// # use rand::{rngs::StdRng, SeedableRng as _};
+    /// let document = Document::try_from_str("{% 1000000000d1000000 %}")?;
+    /// let limits = Limits {
+    ///     max_dice_per_roll: 100,
+    ///     ..Limits::default()
+    /// };
+    /// let mut rng = StdRng::seed_from_u64(0);
+    /// let error = Executor::new(&mut rng, Environment::new()).with_limits(limits).execute(&document).unwrap_err();
+    ///
+    /// assert!(matches!(
+    ///     error,
+    ///     ExecutionError::EvaluationError { error: EvaluationError::DiceLimitExceeded(100), .. }
+    /// ));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.evaluator = self.evaluator.with_limits(limits);
+        self.limits = limits;
+        self
+    }
+
+    /// Resolves embedded macros, which aren't sub-macros of the executed document, from the given library.
+    pub fn with_library(mut self, library: &'a dyn MacroLibrary) -> Self {
+        self.library = Some(library);
+        self
+    }
+
+    /// Executes the main macro of a document.
+    ///
+    /// ```
+    /// # use worp_scroll::{Document, runtime::{Environment, Executor}};
+    /// # use rand::{rngs::StdRng, SeedableRng as _};
+    /// let document = Document::try_from_str("You rolled *{% 2d6 %}*!")?;
+    /// let mut rng = StdRng::seed_from_u64(0);
+    /// let output = Executor::new(&mut rng, Environment::new()).execute(&document)?;
+    ///
+    /// assert!(output.to_string().starts_with("You rolled "));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn execute(&mut self, document: &Document) -> Result<Output, ExecutionError> {
+        self.reset();
+        let result = self.execute_main_macro(document);
+        self.finish(result)
+    }
+
+    /// Executes the named sub-macro of a document.
+    /// The sub-macro can use the variables declared by the main macro, as well as its own.
+    pub fn execute_sub_macro(&mut self, document: &Document, name: &str) -> Result<Output, ExecutionError> {
+        self.reset();
+        let result = self.execute_named_sub_macro(document, name);
+        self.finish(result)
+    }
+
+    /// Executes the named sub-macro of a link's document, using the environment that was captured
+    /// when the link was produced rather than the executor's own.
+    ///
+    /// ```
+    /// # use worp_scroll::{Document, runtime::{Continuation, Environment, Executor, Fragment}};
+    /// # use rand::{rngs::StdRng, SeedableRng as _};
+    /// let document = Document::try_from_str("$x := {% 1d20 %}\n\n[Again](#again)\n\n== #again ==\n$x")?;
+    /// let mut rng = StdRng::seed_from_u64(0);
+    /// let mut executor = Executor::new(&mut rng, Environment::new());
+    /// let continuation = match &executor.execute(&document)?[0] {
+    ///     Fragment::Link { continuation, .. } => continuation.clone(),
+    ///     _ => unreachable!(),
+    /// };
+    ///
+    /// let output = executor.resume(&continuation, "again")?;
+    /// assert_eq!(output.to_string(), continuation.environment.variable("x").unwrap().to_string());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn resume(&mut self, continuation: &Continuation, sub_macro: &str) -> Result<Output, ExecutionError> {
+        self.reset();
+        let result = self.resume_sub_macro(continuation, sub_macro);
+        self.finish(result)
+    }
+
+    /// Answers the prompt for a variable, such as one listed by [`ExecutionError::InputRequired`], to be used by
+    /// the next execution. Answers aren't kept for the executions after it, so each of them is asked again.
+    ///
+    /// ```
+    /// # use worp_scroll::{Document, error::ExecutionError, runtime::{Environment, Executor, Value}};
+    /// # use rand::{rngs::StdRng, SeedableRng as _};
+    /// let document = Document::try_from_str("$arrows := prompt \"How many arrows?\": integer = 1\n\n{% $arrows %}d6")?;
+    /// let mut rng = StdRng::seed_from_u64(0);
+    /// let mut executor = Executor::new(&mut rng, Environment::new());
+    ///
+    /// let request = match executor.execute(&document) {
+    ///     Err(ExecutionError::InputRequired(request)) => request,
+    ///     _ => unreachable!(),
+    /// };
+    /// assert_eq!(request.fields[0].label, "How many arrows?");
+    ///
+    /// executor.answer("arrows", Value::Integer(3));
+    /// assert_eq!(executor.execute(&document)?.to_string(), "3d6");
+    /// assert!(matches!(executor.execute(&document), Err(ExecutionError::InputRequired(_))));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn answer(&mut self, variable: impl Into<String>, value: Value) {
+        self.pending_answers.insert(variable.into(), value);
+    }
+
+    /// Takes the changes made to token attributes by the most recent execution, such as `self.hp -= 8`, for the
+    /// game to apply once it has allowed them. Changes that aren't taken are discarded by the next execution, and an
+    /// execution that fails doesn't make any.
+    pub fn take_changes(&mut self) -> Vec<AttributeChange> {
+        self.evaluator.take_changes()
+    }
+
+    /// Prepares to start a new execution, which has its own call stack, limits, answers and attribute changes.
+    fn reset(&mut self) {
+        self.answers = std::mem::take(&mut self.pending_answers);
+        self.call_stack.clear();
+        self.output_size = 0;
+        self.evaluator.reset_usage();
+        self.evaluator.take_changes();
+    }
+
+    /// Discards the attribute changes of an execution that failed partway, so only finished executions hand any out.
+    fn finish(&mut self, result: Result<Output, ExecutionError>) -> Result<Output, ExecutionError> {
+        if result.is_err() {
+            self.evaluator.take_changes();
+        }
+
+        result
+    }
+
+    fn execute_main_macro(&mut self, document: &Document) -> Result<Output, ExecutionError> {
+        let mut input = self.input_collector();
+        input.definition(document, None, &document.main_macro);
+        input.finish()?;
+
+        let scope = self.enter_document(document, None)?;
+        let mut output = Output::new();
+        self.render_spans(&scope, &document.main_macro.body, &scope.environment, &mut output)?;
+
+        Ok(output)
+    }
+
+    fn execute_named_sub_macro(&mut self, document: &Document, name: &str) -> Result<Output, ExecutionError> {
+        let sub_macro = document
+            .sub_macro(name)
+            .ok_or_else(|| ExecutionError::UndefinedMacro(name.to_owned()))?;
+
+        let mut input = self.input_collector();
+        input.variables(&document.main_macro.variables);
+        input.definition(document, None, sub_macro);
+        input.finish()?;
+
+        let scope = self.enter_document(document, None)?;
+        let mut output = Output::new();
+        self.call_sub_macro(&scope, sub_macro, name, &mut output)?;
+
+        Ok(output)
+    }
+
+    fn resume_sub_macro(&mut self, continuation: &Continuation, sub_macro: &str) -> Result<Output, ExecutionError> {
+        let document = Document::try_from_str(&continuation.source).map_err(ExecutionError::InvalidContinuation)?;
+        let sub_macro_definition = document
+            .sub_macro(sub_macro)
+            .ok_or_else(|| ExecutionError::UndefinedMacro(sub_macro.to_owned()))?;
+
+        let mut input = self.input_collector();
+        input.definition(&document, None, sub_macro_definition);
+        input.finish()?;
+
+        let scope = Scope {
+            document: &document,
+            name: None,
+            environment: continuation.environment.clone(),
+        };
+        let mut output = Output::new();
+        self.call_sub_macro(&scope, sub_macro_definition, sub_macro, &mut output)?;
+
+        Ok(output)
+    }
+
+    fn enter_document<'d>(&mut self, document: &'d Document, name: Option<&str>) -> Result<Scope<'d>, ExecutionError> {
+        let mut environment = self.environment.clone();
+        self.declare_variables(&document.main_macro.variables, &mut environment)?;
+
+        Ok(Scope {
+            document,
+            name: name.map(str::to_owned),
+            environment,
+        })
+    }
+
+    /// Collects the unanswered prompts of every macro the execution can reach.
+    fn input_collector(&self) -> InputCollector<'_> {
+        InputCollector {
+            answers: &self.answers,
+            library: self.library,
+            visited: Vec::new(),
+            fields: Vec::new(),
+            conflict: None,
+        }
+    }
+
+    fn declare_variables(
+        &mut self,
+        variables: &VariableList,
+        environment: &mut Environment,
+    ) -> Result<(), ExecutionError> {
+        for variable in variables.iter() {
+            let name = &variable.name.name;
+
+            match &variable.kind {
+                VariableKind::Expression(expression) => {
+                    let value = self.evaluator.evaluate(expression, environment).map_err(|error| {
+                        ExecutionError::EvaluationError {
+                            error,
+                            location: variable.location,
+                        }
+                    })?;
+
+                    environment.set_variable(name.clone(), value);
+                    environment.set_transcript(name.clone(), self.evaluator.take_transcript());
+                }
+                VariableKind::Prompt(prompt) => {
+                    // Every prompt the execution can reach was asked for before it started.
+                    let answer = self.answers.get(name).ok_or_else(|| {
+                        ExecutionError::InputRequired(InputRequest {
+                            fields: vec![InputField::new(name, prompt)],
+                        })
+                    })?;
+                    let value = answer_value(prompt, answer).ok_or_else(|| ExecutionError::InvalidInput {
+                        variable: name.clone(),
+                        value: answer.clone(),
+                    })?;
+
+                    environment.set_variable(name.clone(), value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Embeds the output of another macro, which is either a sub-macro of the current document
+    /// or the main macro of a document in the library.
+    fn call_macro(&mut self, scope: &Scope<'_>, name: &Symbol, output: &mut Output) -> Result<(), ExecutionError> {
+        if let Some(sub_macro) = scope.document.sub_macro(&name.name) {
+            return self.call_sub_macro(scope, sub_macro, &name.name, output);
+        }
+
+        let document = self
+            .library
+            .and_then(|library| library.get_macro(&name.name))
+            .ok_or_else(|| ExecutionError::UndefinedMacro(name.name.clone()))?;
+
+        self.push_call(MacroCall {
+            document: Some(name.name.clone()),
+            sub_macro: None,
+        })?;
+        let scope = self.enter_document(document, Some(&name.name))?;
+        self.render_spans(&scope, &document.main_macro.body, &scope.environment, output)?;
+        self.call_stack.pop();
+
+        Ok(())
+    }
+
+    fn call_sub_macro(
+        &mut self,
+        scope: &Scope<'_>,
+        sub_macro: &Definition,
+        name: &str,
+        output: &mut Output,
+    ) -> Result<(), ExecutionError> {
+        self.push_call(MacroCall {
+            document: scope.name.clone(),
+            sub_macro: Some(name.to_owned()),
+        })?;
+        let mut environment = scope.environment.clone();
+        self.declare_variables(&sub_macro.variables, &mut environment)?;
+        self.render_spans(scope, &sub_macro.body, &environment, output)?;
+        self.call_stack.pop();
+
+        Ok(())
+    }
+
+    fn push_call(&mut self, call: MacroCall) -> Result<(), ExecutionError> {
+        if let Some(index) = self.call_stack.iter().position(|other| *other == call) {
+            let mut call_stack: Vec<_> = self.call_stack[index..]
+                .iter()
+                .map(|call| call.name().to_owned())
+                .collect();
+            call_stack.push(call.name().to_owned());
+
+            return Err(ExecutionError::RecursiveMacroCall(call_stack));
+        }
+
+        if self.call_stack.len() >= self.limits.max_call_depth {
+            return Err(ExecutionError::CallDepthExceeded(self.limits.max_call_depth));
+        }
+
+        self.call_stack.push(call);
+
+        Ok(())
+    }
+
+    fn render_spans(
+        &mut self,
+        scope: &Scope<'_>,
+        spans: &[Span],
+        environment: &Environment,
+        output: &mut Output,
+    ) -> Result<(), ExecutionError> {
+        for span in spans {
+            self.render_span(scope, span, environment, output)?;
+        }
+
+        Ok(())
+    }
+
+    fn render_span(
+        &mut self,
+        scope: &Scope<'_>,
+        span: &Span,
+        environment: &Environment,
+        output: &mut Output,
+    ) -> Result<(), ExecutionError> {
+        match &span.kind {
+            SpanKind::RawText(text) => {
+                self.count_output(text)?;
+                output.push_text(text);
+            }
+            SpanKind::Expression(expression) => {
+                let value = self.evaluator.evaluate(expression, environment).map_err(|error| {
+                    ExecutionError::EvaluationError {
+                        error,
+                        location: span.location,
+                    }
+                })?;
+                let text = value.to_string();
+                self.count_output(&text)?;
+                output.push_evaluated(text, self.evaluator.take_transcript());
+            }
+            SpanKind::Reference(symbol) if symbol.is_variable() => {
+                let value = environment
+                    .variable(&symbol.name)
+                    .ok_or_else(|| ExecutionError::EvaluationError {
+                        error: EvaluationError::UndefinedVariable(symbol.name.clone()),
+                        location: span.location,
+                    })?;
+                let text = value.to_string();
+                self.count_output(&text)?;
+                let transcript = environment.transcript(&symbol.name).cloned().unwrap_or_default();
+                output.push_evaluated(text, transcript);
+            }
+            SpanKind::Reference(symbol) => self.call_macro(scope, symbol, output)?,
+            SpanKind::BoldText(spans) => {
+                let mut inner = Output::new();
+                self.render_spans(scope, spans, environment, &mut inner)?;
+                output.push(Fragment::Bold(inner));
+            }
+            SpanKind::ItalicText(spans) => {
+                let mut inner = Output::new();
+                self.render_spans(scope, spans, environment, &mut inner)?;
+                output.push(Fragment::Italic(inner));
+            }
+            SpanKind::UnderlineText(spans) => {
+                let mut inner = Output::new();
+                self.render_spans(scope, spans, environment, &mut inner)?;
+                output.push(Fragment::Underline(inner));
+            }
+            SpanKind::StrikeThroughText(spans) => {
+                let mut inner = Output::new();
+                self.render_spans(scope, spans, environment, &mut inner)?;
+                output.push(Fragment::StrikeThrough(inner));
+            }
+            SpanKind::Link(link) => {
+                self.count_output(&link.label)?;
+                output.push(Fragment::Link {
+                    label: link.label.clone(),
+                    target: link.target.clone(),
+                    continuation: Continuation {
+                        source: scope.document.source.clone(),
+                        environment: scope.environment.clone(),
+                    },
+                });
+            }
+            SpanKind::Conditional {
+                condition,
+                body,
+                otherwise,
+            } => {
+                let condition = self
+                    .evaluator
+                    .evaluate(condition, environment)
+                    .and_then(|value| value.as_boolean())
+                    .map_err(|error| ExecutionError::EvaluationError {
+                        error,
+                        location: span.location,
+                    })?;
+
+                let spans = if condition { Some(body) } else { otherwise.as_ref() };
+                let transcript = self.evaluator.take_transcript();
+
+                if transcript.is_empty() {
+                    if let Some(spans) = spans {
+                        self.render_spans(scope, spans, environment, output)?;
+                    }
+                } else {
+                    let mut inner = Output::new();
+
+                    if let Some(spans) = spans {
+                        self.render_spans(scope, spans, environment, &mut inner)?;
+                    }
+
+                    output.push(Fragment::Conditional {
+                        output: inner,
+                        transcript,
+                    });
+                }
+            }
+            SpanKind::EachTarget(spans) => {
+                let targets = match environment.identifier("targets") {
+                    Some(Value::List(targets)) => targets,
+                    value => {
+                        let error = match value {
+                            Some(value) => value.type_mismatch("list"),
+                            None => EvaluationError::UndefinedIdentifier("targets".to_owned()),
+                        };
+
+                        return Err(ExecutionError::EvaluationError {
+                            error,
+                            location: span.location,
+                        });
+                    }
+                };
+
+                // Embedded sub-macros and links see the current target too, so they're bound in the scope as well.
+                for target in targets {
+                    let scope = Scope {
+                        document: scope.document,
+                        name: scope.name.clone(),
+                        environment: scope.environment.clone().with_identifier("target", target.clone()),
+                    };
+                    let environment = environment.clone().with_identifier("target", target.clone());
+                    self.render_spans(&scope, spans, &environment, output)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn count_output(&mut self, text: &str) -> Result<(), ExecutionError> {
+        self.output_size = self.output_size.saturating_add(text.len());
+
+        if self.output_size > self.limits.max_output_size {
+            return Err(ExecutionError::OutputLimitExceeded(self.limits.max_output_size));
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the unanswered prompts of a macro, along with those of every sub-macro and library macro it embeds,
+/// so they're all asked for at once, before any dice are rolled. Running the macro again with the answers then gives
+/// the same results as if it had waited for them.
+///
+/// Answers are given for variable names, so macros that prompt for the same variable share its answer, as long as
+/// they ask the same thing. Prompts for the same variable that differ in any way are reported as a conflict instead.
+struct InputCollector<'e> {
+    answers: &'e BTreeMap<String, Value>,
+    library: Option<&'e dyn MacroLibrary>,
+    visited: Vec<MacroCall>,
+    fields: Vec<InputField>,
+    conflict: Option<String>,
+}
+
+impl InputCollector<'_> {
+    fn variables(&mut self, variables: &VariableList) {
+        for variable in variables.iter() {
+            let name = &variable.name.name;
+
+            if let VariableKind::Prompt(prompt) = &variable.kind {
+                let field = InputField::new(name, prompt);
+
+                match self.fields.iter().find(|other| other.variable == *name) {
+                    Some(other) if *other != field => {
+                        self.conflict.get_or_insert_with(|| name.clone());
+                    }
+                    Some(_) => {}
+                    None => self.fields.push(field),
+                }
+            }
+        }
+    }
+
+    fn definition(&mut self, document: &Document, name: Option<&str>, definition: &Definition) {
+        self.variables(&definition.variables);
+        self.spans(document, name, &definition.body);
+    }
+
+    fn spans(&mut self, document: &Document, name: Option<&str>, spans: &[Span]) {
+        for span in spans {
+            match &span.kind {
+                SpanKind::Reference(symbol) if !symbol.is_variable() => {
+                    self.embedded_macro(document, name, &symbol.name)
+                }
+                SpanKind::BoldText(spans)
+                | SpanKind::ItalicText(spans)
+                | SpanKind::UnderlineText(spans)
+                | SpanKind::StrikeThroughText(spans)
+                | SpanKind::EachTarget(spans) => self.spans(document, name, spans),
+                SpanKind::Conditional { body, otherwise, .. } => {
+                    self.spans(document, name, body);
+
+                    if let Some(otherwise) = otherwise {
+                        self.spans(document, name, otherwise);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Resolves an embedded macro the same way as [`Executor::call_macro`], visiting each macro only once.
+    fn embedded_macro(&mut self, document: &Document, name: Option<&str>, macro_name: &str) {
+        if let Some(sub_macro) = document.sub_macro(macro_name) {
+            let call = MacroCall {
+                document: name.map(str::to_owned),
+                sub_macro: Some(macro_name.to_owned()),
+            };
+
+            if self.visit(call) {
+                self.definition(document, name, sub_macro);
+            }
+        } else if let Some(library_document) = self.library.and_then(|library| library.get_macro(macro_name)) {
+            let call = MacroCall {
+                document: Some(macro_name.to_owned()),
+                sub_macro: None,
+            };
+
+            if self.visit(call) {
+                self.definition(library_document, Some(macro_name), &library_document.main_macro);
+            }
+        }
+    }
+
+    fn visit(&mut self, call: MacroCall) -> bool {
+        if self.visited.contains(&call) {
+            return false;
+        }
+
+        self.visited.push(call);
+        true
+    }
+
+    fn finish(self) -> Result<(), ExecutionError> {
+        if let Some(variable) = self.conflict {
+            return Err(ExecutionError::ConflictingPrompts(variable));
+        }
+
+        let answers = self.answers;
+        let fields: Vec<_> = self
+            .fields
+            .into_iter()
+            .filter(|field| !answers.contains_key(&field.variable))
+            .collect();
+
+        if fields.is_empty() {
+            return Ok(());
+        }
+
+        Err(ExecutionError::InputRequired(InputRequest { fields }))
+    }
+}
+
+/// Converts an answer to the prompt's type, if it's an answer the prompt accepts.
+fn answer_value(prompt: &Prompt, answer: &Value) -> Option<Value> {
+    let literal = match answer {
+        Value::Integer(value) => Literal::Integer(*value),
+        Value::Decimal(value) => Literal::Decimal(*value),
+        Value::Boolean(value) => Literal::Boolean(*value),
+        Value::String(value) => Literal::String(value.clone()),
+        _ => return None,
+    };
+
+    match literal {
+        _ if !prompt.accepts(&literal) => None,
+        Literal::Integer(value) if prompt.value_type == PromptType::Decimal => Some(Value::Decimal(value as f64)),
+        literal => Some(Value::from(&literal)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        runtime::{EvaluationContext, MenuOption, Table, Value},
+        LinkTarget, Location,
+    };
+    use rand::{rngs::StdRng, SeedableRng as _};
+    use std::collections::BTreeMap;
+
+    fn environment() -> Environment {
+        let ability_mods = (1..=20).map(|score| (score, Value::Integer(score / 2 - 5))).collect();
+        let mut token = BTreeMap::new();
+        token.insert("name".to_owned(), Value::String("Gimli".to_owned()));
+        token.insert("strength".to_owned(), Value::Integer(16));
+        token.insert("charisma".to_owned(), Value::Integer(8));
+        let mut global = BTreeMap::new();
+        global.insert(
+            "ability_mods".to_owned(),
+            Value::Table(Table::IntegerKeyed(ability_mods)),
+        );
+
+        Environment::new()
+            .with_identifier("self", Value::Table(Table::StringKeyed(token)))
+            .with_identifier("global", Value::Table(Table::StringKeyed(global)))
+    }
+
+    #[test]
+    fn execute_test_data() {
+        let inputs = &[
+            include_str!("../../test/data/long_sword_basic_attack.txt"),
+            include_str!("../../test/data/long_sword_multiple_attack.txt"),
+            include_str!("../../test/data/eblast.txt"),
+        ];
+
+        for input in inputs {
+            let document = Document::try_from_str(input).unwrap();
+            let mut rng = StdRng::seed_from_u64(0);
+            let mut executor = Executor::new(&mut rng, environment());
+
+            let output = executor.execute(&document).unwrap();
+            assert!(output.to_string().starts_with("Gimli "));
+
+            for sub_macro in document.sub_macros.iter() {
+                let name = &sub_macro.name.as_ref().unwrap().name;
+                executor.execute_sub_macro(&document, name).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn execute_resolves_tables_from_context() {
+        struct Party;
+
+        impl EvaluationContext for Party {
+            fn global_table(&self) -> Value {
+                environment().identifier("global").cloned().unwrap()
+            }
+
+            fn self_table(&self) -> Option<Value> {
+                environment().identifier("self").cloned()
+            }
+
+            fn target_tables(&self) -> Vec<Value> {
+                ["Legolas", "Aragorn"]
+                    .iter()
+                    .map(|name| {
+                        let mut token = BTreeMap::new();
+                        token.insert("name".to_owned(), Value::String(name.to_string()));
+                        Value::Table(Table::StringKeyed(token))
+                    })
+                    .collect()
+            }
+        }
+
+        let document = Document::try_from_str(
+            "{% self.name %} {% global.ability_mods[self.strength] %} {% targets[1].name %} {% target.name %}",
+        )
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let output = Executor::new(&mut rng, Environment::from_context(&Party))
+            .execute(&document)
+            .unwrap();
+
+        assert_eq!(output.to_string(), "Gimli 3 Aragorn Legolas");
+    }
+
+    #[test]
+    fn execute_repeats_blocks_for_each_target() {
+        let targets = ["Goblin A", "Goblin B"]
+            .iter()
+            .map(|name| {
+                let mut token = BTreeMap::new();
+                token.insert("id".to_owned(), Value::String(name.to_lowercase()));
+                token.insert("name".to_owned(), Value::String(name.to_string()));
+                token.insert("hp".to_owned(), Value::Integer(10));
+                Value::Table(Table::StringKeyed(token))
+            })
+            .collect();
+        let targeted = environment().with_identifier("targets", Value::List(targets));
+
+        let document = Document::try_from_str(
+            "$damage := {% 7 %}\n\n{% each target %}{% target.name %} is down to #damage\n{% end %}[Again](#damage)\n\n\
+             == #damage ==\n{% target.hp -= $damage %}",
+        )
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut executor = Executor::new(&mut rng, targeted);
+        let output = executor.execute(&document).unwrap();
+
+        assert_eq!(
+            output.to_string(),
+            "Goblin A is down to 3\nGoblin B is down to 3\nAgain"
+        );
+        let changes: Vec<_> = executor.take_changes().into_iter().map(|change| change.token).collect();
+        assert_eq!(changes, vec!["goblin a", "goblin b"]);
+
+        let document = Document::try_from_str("{% each target %}{% target.name %}{% end %}").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let error = Executor::new(&mut rng, environment()).execute(&document).unwrap_err();
+        assert!(matches!(
+            error,
+            ExecutionError::EvaluationError { error: EvaluationError::UndefinedIdentifier(name), .. } if name == "targets"
+        ));
+    }
+
+    #[test]
+    fn execute_returns_attribute_changes() {
+        let token = |id: &str, hp: i64| {
+            let mut token = BTreeMap::new();
+            token.insert("id".to_owned(), Value::String(id.to_owned()));
+            token.insert("hp".to_owned(), Value::Integer(hp));
+            Value::Table(Table::StringKeyed(token))
+        };
+        let environment = Environment::new()
+            .with_identifier("self", token("gimli", 20))
+            .with_identifier("targets", Value::List(vec![token("orc", 7), token("troll", 30)]));
+
+        let document = Document::try_from_str(
+            "$damage := {% 8 %}
+
+{% self.hp -= $damage %} {% self.hp -= 3 %} {% targets[1].hp = 4 %}",
+        )
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut executor = Executor::new(&mut rng, environment);
+        let output = executor.execute(&document).unwrap();
+
+        assert_eq!(output.to_string(), "12 9 4");
+        assert_eq!(
+            executor.take_changes(),
+            vec![
+                AttributeChange {
+                    token: "gimli".to_owned(),
+                    attribute: "hp".to_owned(),
+                    previous: Some(Value::Integer(20)),
+                    value: Value::Integer(12),
+                },
+                AttributeChange {
+                    token: "gimli".to_owned(),
+                    attribute: "hp".to_owned(),
+                    previous: Some(Value::Integer(12)),
+                    value: Value::Integer(9),
+                },
+                AttributeChange {
+                    token: "troll".to_owned(),
+                    attribute: "hp".to_owned(),
+                    previous: Some(Value::Integer(30)),
+                    value: Value::Integer(4),
+                },
+            ]
+        );
+        assert!(executor.take_changes().is_empty());
+
+        let inputs = &[
+            ("{% self = 3 %}", EvaluationError::InvalidAssignment("self".to_owned())),
+            (
+                "{% self.hp.max = 3 %}",
+                EvaluationError::InvalidAssignment("self.hp.max".to_owned()),
+            ),
+            ("{% self.ac += 1 %}", EvaluationError::KeyNotFound("ac".to_owned())),
+            (
+                "{% self.hp = \"full\" %}",
+                EvaluationError::TypeMismatch {
+                    expected: "number",
+                    found: "string",
+                },
+            ),
+            ("{% targets[2].hp = 0 %}", EvaluationError::IndexOutOfBounds(2)),
+        ];
+
+        for (input, expected) in inputs {
+            let document = Document::try_from_str(input).unwrap();
+            let error = executor.execute(&document).unwrap_err();
+
+            assert!(
+                matches!(&error, ExecutionError::EvaluationError { error, .. } if error == expected),
+                "{}: {:?}",
+                input,
+                error
+            );
+        }
+
+        let document = Document::try_from_str("{% self.hp -= 5 %} {% targets[2].hp = 0 %}").unwrap();
+        assert!(executor.execute(&document).is_err());
+        assert!(executor.take_changes().is_empty());
+    }
+
+    #[test]
+    fn execute_shows_conditional_blocks() {
+        let document = Document::try_from_str(
+            "$attack := {% 1d20 %}\n\
+             \n\
+             {% if $attack == 20 %}*Critical hit!* [Damage](#crit){% else %}[Damage](#damage){% end %}\n\
+             \n\
+             == #damage ==\n\
+             {% 1d8 %}\n\
+             \n\
+             == #crit ==\n\
+             {% 2d8 %}",
+        )
+        .unwrap();
+        /// Rolls the same value on every die.
+        struct Always(u64);
+
+        impl DiceRng for Always {
+            fn roll_die(&mut self, _sides: u64) -> u64 {
+                self.0
+            }
+        }
+
+        let critical = |roll: u64| {
+            let mut rng = Always(roll);
+            let output = Executor::new(&mut rng, Environment::new()).execute(&document).unwrap();
+
+            // The condition's transcript shows the roll of `$attack` it depends on.
+            let (output, transcript) = match output.last() {
+                Some(Fragment::Conditional { output, transcript }) => (output.clone(), transcript.clone()),
+                fragment => panic!("expected a conditional block, found {:?}", fragment),
+            };
+            assert!(!transcript.is_empty());
+
+            match output.last() {
+                Some(Fragment::Link {
+                    target: LinkTarget::Target(target),
+                    ..
+                }) => target.name == "crit",
+                fragment => panic!("expected a link, found {:?}", fragment),
+            }
+        };
+
+        assert!(critical(20));
+        assert!(!critical(19));
+
+        let document = Document::try_from_str("a{% if 1d20 > 20 %}never{% end %}{% if true %}b{% end %}").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let output = Executor::new(&mut rng, Environment::new()).execute(&document).unwrap();
+        assert_eq!(output.len(), 3);
+        assert!(matches!(
+            &output[1],
+            Fragment::Conditional { output, transcript } if output.is_empty() && !transcript.is_empty()
+        ));
+        assert_eq!(output.to_string(), "ab");
+
+        let document = Document::try_from_str("{% if 1 %}one{% end %}").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let error = Executor::new(&mut rng, Environment::new())
+            .execute(&document)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ExecutionError::EvaluationError {
+                error: EvaluationError::TypeMismatch {
+                    expected: "boolean",
+                    ..
+                },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn execute_asks_for_prompt_input() {
+        let document = Document::try_from_str(
+            "$bonus := prompt \"Situational bonus\": decimal = 0\n\
+             $mode  := prompt \"Mode\": string [\"melee\", \"ranged\"]\n\
+             $total := {% $bonus + 1 %}\n\
+             \n\
+             {% $mode %} $total",
+        )
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut executor = Executor::new(&mut rng, Environment::new());
+
+        let request = match executor.execute(&document) {
+            Err(ExecutionError::InputRequired(request)) => request,
+            result => panic!("expected a request for input, found {:?}", result),
+        };
+        assert_eq!(
+            request.fields,
+            vec![
+                InputField {
+                    variable: "bonus".to_owned(),
+                    label: "Situational bonus".to_owned(),
+                    value_type: PromptType::Decimal,
+                    default: Some(Value::Integer(0)),
+                    choices: Vec::new(),
+                },
+                InputField {
+                    variable: "mode".to_owned(),
+                    label: "Mode".to_owned(),
+                    value_type: PromptType::String,
+                    default: None,
+                    choices: vec![Value::String("melee".to_owned()), Value::String("ranged".to_owned())],
+                },
+            ]
+        );
+
+        executor.answer("bonus", Value::Integer(2));
+        let request = match executor.execute(&document) {
+            Err(ExecutionError::InputRequired(request)) => request,
+            result => panic!("expected a request for input, found {:?}", result),
+        };
+        assert_eq!(request.fields.len(), 1);
+        assert_eq!(request.fields[0].variable, "mode");
+
+        executor.answer("bonus", Value::Integer(2));
+        executor.answer("mode", Value::String("thrown".to_owned()));
+        assert_eq!(
+            executor.execute(&document).unwrap_err(),
+            ExecutionError::InvalidInput {
+                variable: "mode".to_owned(),
+                value: Value::String("thrown".to_owned()),
+            }
+        );
+
+        executor.answer("bonus", Value::Integer(2));
+        executor.answer("mode", Value::String("ranged".to_owned()));
+        assert_eq!(executor.execute(&document).unwrap().to_string(), "ranged 3");
+
+        // Answers are only used by the execution after they're given.
+        assert!(matches!(
+            executor.execute(&document),
+            Err(ExecutionError::InputRequired(request)) if request.fields.len() == 2
+        ));
+    }
+
+    #[test]
+    fn execute_asks_for_embedded_prompts_up_front() {
+        let document = Document::try_from_str(
+            "$roll := {% 1d20 %}\n\
+             \n\
+             {% if $roll > 0 %}#aim{% end %} #library [Again](#again)\n\
+             \n\
+             == #aim ==\n\
+             $bonus := prompt \"Bonus\": integer\n\
+             \n\
+             {% $roll + $bonus %}\n\
+             \n\
+             == #again ==\n\
+             $reroll := prompt \"Reroll?\": boolean\n\
+             \n\
+             $reroll",
+        )
+        .unwrap();
+        let mut library = BTreeMap::new();
+        library.insert(
+            "library".to_owned(),
+            Document::try_from_str("$target := prompt \"Target\": string\n\n$target").unwrap(),
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut executor = Executor::new(&mut rng, Environment::new()).with_library(&library);
+
+        // Linked sub-macros aren't run by the execution, so their prompts are asked for once the link is followed.
+        let request = match executor.execute(&document) {
+            Err(ExecutionError::InputRequired(request)) => request,
+            result => panic!("expected a request for input, found {:?}", result),
+        };
+        let variables: Vec<_> = request.fields.iter().map(|field| field.variable.as_str()).collect();
+        assert_eq!(variables, ["bonus", "target"]);
+
+        executor.answer("bonus", Value::Integer(100));
+        executor.answer("target", Value::String("orc".to_owned()));
+        let output = executor.execute(&document).unwrap().to_string();
+        let (total, rest) = output.split_at(output.find(' ').unwrap());
+        assert!((101..=120).contains(&total.parse::<i64>().unwrap()), "{}", output);
+        assert_eq!(rest, " orc Again");
+
+        // The same prompt can be shared by several macros, but two different prompts for `$bonus` can't.
+        let shared = "$bonus := prompt \"Bonus\": integer\n\n#aim {% $bonus %}\n\n== #aim ==\n\
+                      $bonus := prompt \"Bonus\": integer\n\n{% $bonus %}";
+        executor.answer("bonus", Value::Integer(2));
+        let output = executor.execute(&Document::try_from_str(shared).unwrap()).unwrap();
+        assert_eq!(output.to_string(), "2 2");
+
+        let conflicting = "$bonus := prompt \"Bonus\": integer\n\n#aim\n\n== #aim ==\n\
+                           $bonus := prompt \"Bonus\": boolean\n\n{% $bonus %}";
+        executor.answer("bonus", Value::Integer(2));
+        assert_eq!(
+            executor.execute(&Document::try_from_str(conflicting).unwrap()),
+            Err(ExecutionError::ConflictingPrompts("bonus".to_owned()))
+        );
+    }
+
+    #[test]
+    fn execute_preserves_formatting() {
+        let document = Document::try_from_str("$x := {% 2 + 3 %}\n\n*a ~$x~* and _{% $x * 2 %}_ [Go](#next)").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let output = Executor::new(&mut rng, Environment::new()).execute(&document).unwrap();
+
+        let mut bold = Output::new();
+        bold.push_text("a ");
+        bold.push(Fragment::Italic({
+            let mut italic = Output::new();
+            italic.push_text("5");
+            italic
+        }));
+
+        assert_eq!(output[0], Fragment::Bold(bold));
+        assert_eq!(output[1], Fragment::Text(" and ".to_owned()));
+        assert_eq!(
+            output[2],
+            Fragment::Underline({
+                let mut underline = Output::new();
+                underline.push_text("10");
+                underline
+            })
+        );
+        assert!(matches!(&output[4], Fragment::Link { label, .. } if label == "Go"));
+        assert_eq!(output.to_string(), "a 5 and 10 Go");
+    }
+
+    #[test]
+    fn execute_reports_evaluation_errors() {
+        let document = Document::try_from_str("{% self.name %}").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let result = Executor::new(&mut rng, Environment::new()).execute(&document);
+
+        assert_eq!(
+            result,
+            Err(ExecutionError::EvaluationError {
+                error: EvaluationError::UndefinedIdentifier("self".to_owned()),
+                location: Location {
+                    start: 0,
+                    end: 15,
+                    line: 1,
+                    column: 1,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn execute_embeds_macros() {
+        let document =
+            Document::try_from_str("$x := {% 1 %}\n\n#inner\n\n== #inner ==\n$y := {% $x + 1 %}\n\n$y and #library")
+                .unwrap();
+        // Sub-macros of different documents can share a name without being mistaken for a recursive call.
+        let mut library = BTreeMap::new();
+        library.insert(
+            "library".to_owned(),
+            Document::try_from_str("$x := {% 10 %}\n\n*$x* #inner\n\n== #inner ==\n{% $x * 2 %}").unwrap(),
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        let output = Executor::new(&mut rng, Environment::new())
+            .with_library(&library)
+            .execute(&document)
+            .unwrap();
+
+        assert_eq!(output.to_string(), "2 and 10 20");
+    }
+
+    #[test]
+    fn execute_reports_recursive_macro_calls() {
+        let document = Document::try_from_str("#a\n\n== #a ==\n#b\n\n== #b ==\n#c\n\n== #c ==\n#a").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut executor = Executor::new(&mut rng, Environment::new());
+
+        let call_stack = vec!["a".to_owned(), "b".to_owned(), "c".to_owned(), "a".to_owned()];
+        assert_eq!(
+            executor.execute(&document),
+            Err(ExecutionError::RecursiveMacroCall(call_stack))
+        );
+        assert_eq!(
+            executor.execute(&Document::try_from_str("#missing").unwrap()),
+            Err(ExecutionError::UndefinedMacro("missing".to_owned()))
+        );
+
+        let mut library = BTreeMap::new();
+        library.insert("loop".to_owned(), Document::try_from_str("#loop").unwrap());
+        let mut rng = StdRng::seed_from_u64(0);
+        let result = Executor::new(&mut rng, Environment::new())
+            .with_library(&library)
+            .execute(&Document::try_from_str("#loop").unwrap());
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Macro calls itself recursively: #loop -> #loop."
+        );
+    }
+
+    #[test]
+    fn execute_enforces_limits() {
+        let execute = |input: &str, limits: Limits| {
+            let mut rng = StdRng::seed_from_u64(0);
+            Executor::new(&mut rng, Environment::new())
+                .with_limits(limits)
+                .execute(&Document::try_from_str(input).unwrap())
+        };
+        let evaluation_error = |result: Result<Output, ExecutionError>| match result {
+            Err(ExecutionError::EvaluationError { error, .. }) => error,
+            result => panic!("expected an evaluation error, found {:?}", result),
+        };
+
+        assert_eq!(
+            evaluation_error(execute("{% 1000000000d1000000 %}", Limits::default())),
+            EvaluationError::DiceLimitExceeded(1_000)
+        );
+        assert_eq!(
+            evaluation_error(execute("{% 1=..=1000000 %}", Limits::default())),
+            EvaluationError::StepLimitExceeded(100_000)
+        );
+
+        // Each variable doubles the length of the previous one, which would otherwise grow without bound.
+        let doubling: String = (1..=20)
+            .map(|index| format!("$s{} := {{% $s{} + $s{} %}}\n", index, index - 1, index - 1))
+            .collect();
+        assert_eq!(
+            evaluation_error(execute(
+                &format!("$s0 := {{% \"ab\" %}}\n{}\n$s20", doubling),
+                Limits::default()
+            )),
+            EvaluationError::ValueSizeLimitExceeded(64 * 1024)
+        );
+
+        let limits = Limits {
+            max_total_dice: 10,
+            ..Limits::default()
+        };
+        assert_eq!(
+            evaluation_error(execute("{% 6d6 %} {% 6d6 %}", limits)),
+            EvaluationError::TotalDiceLimitExceeded(10)
+        );
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut executor = Executor::new(&mut rng, Environment::new()).with_limits(limits);
+        let document = Document::try_from_str("{% 6d6 %}").unwrap();
+        assert!(executor.execute(&document).is_ok());
+        assert!(executor.execute(&document).is_ok());
+
+        let nested = "#a\n\n== #a ==\n#b\n\n== #b ==\n#c\n\n== #c ==\ndone";
+        let limits = Limits {
+            max_call_depth: 2,
+            ..Limits::default()
+        };
+        assert_eq!(execute(nested, limits), Err(ExecutionError::CallDepthExceeded(2)));
+
+        let limits = Limits {
+            max_output_size: 10,
+            ..Limits::default()
+        };
+        assert!(execute("{% 12345 %} done", limits).is_ok());
+        assert_eq!(
+            execute("{% 12345 %} and more", limits),
+            Err(ExecutionError::OutputLimitExceeded(10))
+        );
+    }
+
+    #[test]
+    fn resume_uses_captured_variables() {
+        let input = "$attack := {% 1d20 %}\n$damage := {% 1d1000 %}\n\n\
+                     $attack [Next](\"Damage\": #damage, \"Again\": #again)\n\n\
+                     == #damage ==\n$bonus := {% 2 %}\n\n{% $damage + $bonus %}\n\n\
+                     == #again ==\n#damage";
+        let document = Document::try_from_str(input).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let output = Executor::new(&mut rng, environment()).execute(&document).unwrap();
+
+        let continuation = match &output[2] {
+            Fragment::Link { continuation, .. } => continuation,
+            fragment => panic!("expected a link, found {:?}", fragment),
+        };
+        assert_eq!(continuation.source, input);
+
+        let json = serde_json::to_string(continuation).unwrap();
+        let continuation: Continuation = serde_json::from_str(&json).unwrap();
+        let damage = continuation
+            .environment
+            .variable("damage")
+            .unwrap()
+            .as_integer()
+            .unwrap();
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut executor = Executor::new(&mut rng, Environment::new());
+        for sub_macro in &["damage", "again"] {
+            let output = executor.resume(&continuation, sub_macro).unwrap();
+            assert_eq!(output.to_string(), (damage + 2).to_string());
+        }
+        assert_eq!(
+            executor.resume(&continuation, "missing"),
+            Err(ExecutionError::UndefinedMacro("missing".to_owned()))
+        );
+    }
+
+    #[test]
+    fn menus_execute_selected_options() {
+        let input = "$x := {% 3 %}\n\n[Go](#first) [Choose](\"First\": #first, \"Second\": #second)\n\n\
+                     == #first ==\n{% $x %}\n\n== #second ==\n{% $x * 2 %}";
+        let document = Document::try_from_str(input).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut executor = Executor::new(&mut rng, Environment::new());
+        let output = executor.execute(&document).unwrap();
+
+        assert_eq!(output[1].menu(), None);
+
+        let simple = output[0].menu().unwrap();
+        assert!(simple.immediate);
+        assert_eq!(
+            simple.options,
+            vec![MenuOption {
+                label: "Go".to_owned(),
+                sub_macro: "first".to_owned(),
+            }]
+        );
+
+        let menu = output[2].menu().unwrap();
+        let labels: Vec<_> = menu.options.iter().map(|option| option.label.as_str()).collect();
+        assert!(!menu.immediate);
+        assert_eq!(labels, vec!["First", "Second"]);
+        assert_eq!(menu.select(&mut executor, 1).unwrap().to_string(), "6");
+        assert_eq!(menu.select(&mut executor, 2), Err(ExecutionError::UnknownMenuOption(2)));
+    }
+
+    #[test]
+    fn execute_attaches_transcripts() {
+        let document = Document::try_from_str("$x := {% 1d4 %}\n\n$x, {% $x + 1 %} and {% 1 + 1 %}").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let output = Executor::new(&mut rng, Environment::new()).execute(&document).unwrap();
+
+        let transcripts: Vec<_> = output
+            .iter()
+            .filter_map(|fragment| match fragment {
+                Fragment::Evaluated { transcript, .. } => Some(transcript.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(transcripts, vec![1, 2]);
+        assert!(output.to_string().ends_with(" and 2"));
+    }
+}
@@ -0,0 +1,91 @@
+use super::Value;
+use crate::error::EvaluationError;
+use std::convert::TryFrom;
+
+/// Calls one of the built-in DICE functions with already evaluated arguments.
+pub(crate) fn call(function: &str, arguments: Vec<Value>) -> Result<Value, EvaluationError> {
+    match function {
+        "sum" => {
+            let [list] = expect_arguments::<1>(function, arguments)?;
+            let total = integers(&list)?
+                .into_iter()
+                .try_fold(0i64, |total, value| total.checked_add(value))
+                .ok_or(EvaluationError::Overflow)?;
+
+            Ok(Value::Integer(total))
+        }
+        "len" => {
+            let [list] = expect_arguments::<1>(function, arguments)?;
+            let length = match &list {
+                Value::List(values) => values.len(),
+                Value::Roll(roll) => roll.values().count(),
+                Value::String(value) => value.chars().count(),
+                _ => return Err(list.type_mismatch("list")),
+            };
+
+            Ok(Value::Integer(length as i64))
+        }
+        "min" | "max" => {
+            let values = match arguments.len() {
+                1 => integers(&arguments[0])?,
+                _ => arguments.iter().map(Value::as_integer).collect::<Result<_, _>>()?,
+            };
+            let result = if function == "min" {
+                values.into_iter().min()
+            } else {
+                values.into_iter().max()
+            };
+
+            result.map(Value::Integer).ok_or(EvaluationError::EmptyList)
+        }
+        "abs" => {
+            let [value] = expect_arguments::<1>(function, arguments)?;
+
+            match value {
+                Value::Decimal(value) => Ok(Value::Decimal(value.abs())),
+                value => value
+                    .as_integer()?
+                    .checked_abs()
+                    .map(Value::Integer)
+                    .ok_or(EvaluationError::Overflow),
+            }
+        }
+        "floor" | "ceil" | "round" => {
+            let [value] = expect_arguments::<1>(function, arguments)?;
+            let value = match value {
+                Value::Decimal(value) => value,
+                value => return value.as_integer().map(Value::Integer),
+            };
+            let rounded = match function {
+                "floor" => value.floor(),
+                "ceil" => value.ceil(),
+                _ => value.round(),
+            };
+
+            if rounded.is_finite() && rounded.abs() < i64::MAX as f64 {
+                Ok(Value::Integer(rounded as i64))
+            } else {
+                Err(EvaluationError::Overflow)
+            }
+        }
+        _ => Err(EvaluationError::UndefinedFunction(function.to_owned())),
+    }
+}
+
+fn expect_arguments<const N: usize>(function: &str, arguments: Vec<Value>) -> Result<[Value; N], EvaluationError> {
+    let found = arguments.len();
+
+    <[Value; N]>::try_from(arguments).map_err(|_| EvaluationError::ArgumentCount {
+        function: function.to_owned(),
+        expected: N,
+        found,
+    })
+}
+
+fn integers(value: &Value) -> Result<Vec<i64>, EvaluationError> {
+    match value {
+        Value::List(values) => values.iter().map(Value::as_integer).collect(),
+        Value::Roll(roll) => Ok(roll.values().collect()),
+        _ => Err(value.type_mismatch("list")),
+    }
+}
@@ -0,0 +1,36 @@
+use super::Value;
+use crate::{Prompt, PromptType};
+use serde::{Deserialize, Serialize};
+
+/// The prompts a macro needs answered before it can run, such as a situational bonus or how many arrows to fire.
+///
+/// Each answer is given to the executor with [`Executor::answer`](super::Executor::answer), after which the macro
+/// can be executed again, either by the player in a form or by a headless client.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct InputRequest {
+    pub fields: Vec<InputField>,
+}
+
+/// A single prompt waiting to be answered, along with the answers it accepts.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct InputField {
+    /// The name of the variable that's given the answer, without the `$`.
+    pub variable: String,
+    pub label: String,
+    pub value_type: PromptType,
+    pub default: Option<Value>,
+    /// The only answers allowed, or any answer of the right type if empty.
+    pub choices: Vec<Value>,
+}
+
+impl InputField {
+    pub fn new(variable: &str, prompt: &Prompt) -> Self {
+        Self {
+            variable: variable.to_owned(),
+            label: prompt.label.clone(),
+            value_type: prompt.value_type,
+            default: prompt.default.as_ref().map(Value::from),
+            choices: prompt.choices.iter().map(Value::from).collect(),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use crate::Document;
+use std::collections::{BTreeMap, HashMap};
+
+/// A collection of named macro documents, such as the macros saved by a player,
+/// which can be embedded in other macros with `#name`.
+pub trait MacroLibrary {
+    fn get_macro(&self, name: &str) -> Option<&Document>;
+}
+
+impl MacroLibrary for BTreeMap<String, Document> {
+    fn get_macro(&self, name: &str) -> Option<&Document> {
+        self.get(name)
+    }
+}
+
+impl MacroLibrary for HashMap<String, Document> {
+    fn get_macro(&self, name: &str) -> Option<&Document> {
+        self.get(name)
+    }
+}
@@ -0,0 +1,45 @@
+/// Limits on the work done while executing a macro, so a malicious or buggy macro can't freeze the server.
+/// Exceeding any of them stops execution with an error naming the limit that was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The most dice a single roll may roll, including dice rolled by rerolls and explosions.
+    pub max_dice_per_roll: u64,
+    /// The most dice that may be rolled across every roll of an execution.
+    pub max_total_dice: u64,
+    /// How deeply macros may embed other macros, counting each embedded macro and sub-macro.
+    pub max_call_depth: usize,
+    /// The most bytes of text an execution may produce.
+    pub max_output_size: usize,
+    /// The most bytes of text a single value may hold, such as a string built by adding strings together.
+    pub max_value_size: usize,
+    /// The most steps that may be taken evaluating expressions, where each expression evaluated is one step
+    /// and each value produced by a range is another.
+    pub max_steps: u64,
+}
+
+impl Limits {
+    /// Limits that are never reached, for trusted macros.
+    pub fn unlimited() -> Self {
+        Self {
+            max_dice_per_roll: u64::MAX,
+            max_total_dice: u64::MAX,
+            max_call_depth: usize::MAX,
+            max_output_size: usize::MAX,
+            max_value_size: usize::MAX,
+            max_steps: u64::MAX,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_dice_per_roll: 1_000,
+            max_total_dice: 10_000,
+            max_call_depth: 16,
+            max_output_size: 64 * 1024,
+            max_value_size: 64 * 1024,
+            max_steps: 100_000,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use super::{Continuation, Executor, Output};
+use crate::{error::ExecutionError, LinkTarget};
+use serde::{Deserialize, Serialize};
+
+/// The choices presented to a player when they click a link in a macro's output.
+///
+/// Simple links, such as `[Roll Damage](#roll_damage)`, produce a menu with a single option that should be executed
+/// as soon as the link is clicked. Multi-action links produce a menu with an option for each of their labeled
+/// targets, in the order they were written.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Menu {
+    pub label: String,
+    pub immediate: bool,
+    pub options: Vec<MenuOption>,
+    pub continuation: Continuation,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MenuOption {
+    pub label: String,
+    pub sub_macro: String,
+}
+
+impl Menu {
+    pub fn new(label: &str, target: &LinkTarget, continuation: Continuation) -> Self {
+        let (immediate, options) = match target {
+            LinkTarget::Target(target) => (
+                true,
+                vec![MenuOption {
+                    label: label.to_owned(),
+                    sub_macro: target.name.clone(),
+                }],
+            ),
+            LinkTarget::TargetList(targets) => (
+                false,
+                targets
+                    .iter()
+                    .map(|labeled_target| MenuOption {
+                        label: labeled_target.label.clone(),
+                        sub_macro: labeled_target.target.name.clone(),
+                    })
+                    .collect(),
+            ),
+        };
+
+        Self {
+            label: label.to_owned(),
+            immediate,
+            options,
+            continuation,
+        }
+    }
+
+    /// Executes the sub-macro of the option at the given index.
+    pub fn select(&self, executor: &mut Executor<'_>, index: usize) -> Result<Output, ExecutionError> {
+        let option = self
+            .options
+            .get(index)
+            .ok_or(ExecutionError::UnknownMenuOption(index))?;
+
+        executor.resume(&self.continuation, &option.sub_macro)
+    }
+}
@@ -0,0 +1,35 @@
+mod analyzer;
+mod change;
+mod context;
+mod continuation;
+mod dice;
+mod distribution;
+mod environment;
+mod evaluator;
+mod executor;
+mod function;
+mod input;
+mod library;
+mod limits;
+mod menu;
+mod output;
+mod rng;
+mod transcript;
+mod value;
+
+pub use analyzer::Analyzer;
+pub use change::AttributeChange;
+pub use context::EvaluationContext;
+pub use continuation::Continuation;
+pub use distribution::Distribution;
+pub use environment::Environment;
+pub use evaluator::Evaluator;
+pub use executor::Executor;
+pub use input::{InputField, InputRequest};
+pub use library::MacroLibrary;
+pub use limits::Limits;
+pub use menu::{Menu, MenuOption};
+pub use output::{Fragment, Output};
+pub use rng::DiceRng;
+pub use transcript::{Step, Transcript};
+pub use value::{Die, DieStatus, Outcome, Roll, Table, Value};
@@ -0,0 +1,118 @@
+use super::{Continuation, Menu, Transcript};
+use crate::LinkTarget;
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+/// The formatted result of executing a macro, ready to be displayed in chat.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Output(Vec<Fragment>);
+
+impl Output {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends text to the output, merging it into the last fragment if that fragment is also text.
+    pub fn push_text(&mut self, text: &str) {
+        match self.0.last_mut() {
+            Some(Fragment::Text(last)) => last.push_str(text),
+            _ => self.0.push(Fragment::Text(text.to_owned())),
+        }
+    }
+
+    /// Appends the result of an expression, which is kept as plain text unless dice were rolled to produce it.
+    pub fn push_evaluated(&mut self, text: String, transcript: Transcript) {
+        if transcript.is_empty() {
+            self.push_text(&text);
+        } else {
+            self.0.push(Fragment::Evaluated { text, transcript });
+        }
+    }
+
+    pub fn push(&mut self, fragment: Fragment) {
+        match fragment {
+            Fragment::Text(text) => self.push_text(&text),
+            fragment => self.0.push(fragment),
+        }
+    }
+}
+
+impl Deref for Output {
+    type Target = [Fragment];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Output {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Renders the output as plain text, with all formatting removed.
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for fragment in self.iter() {
+            write!(f, "{}", fragment)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fragment {
+    Text(String),
+    /// The result of an expression that rolled dice, along with the transcript of how it was rolled.
+    Evaluated {
+        text: String,
+        transcript: Transcript,
+    },
+    Bold(Output),
+    Italic(Output),
+    Underline(Output),
+    StrikeThrough(Output),
+    /// The shown branch of a conditional block whose condition rolled dice, along with the transcript of how they
+    /// were rolled. The output is empty if no branch was shown.
+    Conditional {
+        output: Output,
+        transcript: Transcript,
+    },
+    Link {
+        label: String,
+        target: LinkTarget,
+        continuation: Continuation,
+    },
+}
+
+impl Fragment {
+    /// The menu to present when the fragment is clicked, if it's a link.
+    pub fn menu(&self) -> Option<Menu> {
+        match self {
+            Fragment::Link {
+                label,
+                target,
+                continuation,
+            } => Some(Menu::new(label, target, continuation.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Fragment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fragment::Text(text) | Fragment::Evaluated { text, .. } => write!(f, "{}", text),
+            Fragment::Bold(output)
+            | Fragment::Italic(output)
+            | Fragment::Underline(output)
+            | Fragment::StrikeThrough(output)
+            | Fragment::Conditional { output, .. } => write!(f, "{}", output),
+            Fragment::Link { label, .. } => write!(f, "{}", label),
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use rand::{Rng as _, RngCore};
+
+/// The source of randomness used when rolling dice.
+///
+/// This is implemented for every `rand::RngCore`, so a seeded generator such as `rand::rngs::StdRng` can be used
+/// for reproducible rolls, while `rand::rngs::OsRng` can be used where rolls must not be predictable.
+pub trait DiceRng {
+    /// Rolls a single die, producing a value in the range `1..=sides`.
+    fn roll_die(&mut self, sides: u64) -> u64;
+}
+
+impl<R: RngCore + ?Sized> DiceRng for R {
+    fn roll_die(&mut self, sides: u64) -> u64 {
+        self.gen_range(1..=sides)
+    }
+}
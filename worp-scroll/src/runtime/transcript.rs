@@ -0,0 +1,81 @@
+use super::{Roll, Value};
+use serde::{Deserialize, Serialize};
+use std::{fmt, ops::Deref};
+
+/// A breakdown of how an expression involving dice was evaluated, such as the value shown by each die rolled
+/// and the intermediate totals computed from them.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Transcript(Vec<Step>);
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, step: Step) {
+        self.0.push(step);
+    }
+}
+
+impl Deref for Transcript {
+    type Target = [Step];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Extend<Step> for Transcript {
+    fn extend<T: IntoIterator<Item = Step>>(&mut self, steps: T) {
+        self.0.extend(steps);
+    }
+}
+
+/// Renders each step of the transcript on its own line, such as:
+///
+/// ```text
+/// 1d8: [5] = 5
+/// 1d8 + 2 = 7
+/// ```
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, step) in self.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{}", step)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Step {
+    /// Dice were rolled by the expression.
+    Roll { expression: String, roll: Roll },
+    /// A result was computed from dice rolled earlier in the transcript.
+    Total { expression: String, value: Value },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Roll { expression, roll } => {
+                write!(f, "{}: [", expression)?;
+
+                for (index, die) in roll.dice.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", die)?;
+                }
+
+                write!(f, "] = {}", Value::Roll(roll.clone()))
+            }
+            Step::Total { expression, value } => write!(f, "{} = {}", expression, value),
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use crate::{error::EvaluationError, Literal};
+use serde::{ser::SerializeMap as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::BTreeMap, fmt};
+
+/// A value produced by evaluating a DICE expression.
+///
+/// Values are stored as the plain values of formats such as TOML and JSON, so `critical = true` is a boolean and
+/// `hp = 12` is an integer. Rolls are tried before tables, so they keep their dice when read back.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    Integer(i64),
+    Decimal(f64),
+    Boolean(bool),
+    String(String),
+    Roll(Roll),
+    Table(Table),
+    List(Vec<Value>),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "integer",
+            Value::Decimal(_) => "decimal",
+            Value::Boolean(_) => "boolean",
+            Value::String(_) => "string",
+            Value::Table(_) => "table",
+            Value::List(_) => "list",
+            Value::Roll(_) => "roll",
+        }
+    }
+
+    /// Converts the value to an integer, with rolls producing their total.
+    pub fn as_integer(&self) -> Result<i64, EvaluationError> {
+        match self {
+            Value::Integer(value) => Ok(*value),
+            Value::Roll(roll) => roll.total(),
+            _ => Err(self.type_mismatch("integer")),
+        }
+    }
+
+    /// Converts the value to a decimal, with integers and the totals of rolls converted as needed.
+    pub fn as_decimal(&self) -> Result<f64, EvaluationError> {
+        match self {
+            Value::Decimal(value) => Ok(*value),
+            Value::Integer(_) | Value::Roll(_) => Ok(self.as_integer()? as f64),
+            _ => Err(self.type_mismatch("number")),
+        }
+    }
+
+    pub fn as_boolean(&self) -> Result<bool, EvaluationError> {
+        match self {
+            Value::Boolean(value) => Ok(*value),
+            _ => Err(self.type_mismatch("boolean")),
+        }
+    }
+
+    /// Whether the value is written as a table, which TOML requires to come after every other value.
+    fn is_table(&self) -> bool {
+        match self {
+            Value::Table(_) | Value::Roll(_) => true,
+            Value::List(values) => !values.is_empty() && values.iter().all(Value::is_table),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn type_mismatch(&self, expected: &'static str) -> EvaluationError {
+        EvaluationError::TypeMismatch {
+            expected,
+            found: self.type_name(),
+        }
+    }
+}
+
+impl From<&Literal> for Value {
+    fn from(literal: &Literal) -> Self {
+        match literal {
+            Literal::Integer(value) => Value::Integer(*value),
+            Literal::Decimal(value) => Value::Decimal(*value),
+            Literal::Boolean(value) => Value::Boolean(*value),
+            Literal::String(value) => Value::String(value.clone()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Decimal(value) => write!(f, "{}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Table(table) => write!(f, "{}", table),
+            Value::List(values) => {
+                write!(f, "[")?;
+
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", value)?;
+                }
+
+                write!(f, "]")
+            }
+            Value::Roll(roll) if !roll.symbols.is_empty() => {
+                let tallies: Vec<_> = roll
+                    .symbols
+                    .iter()
+                    .map(|(symbol, count)| format!("{}: {}", symbol, count))
+                    .collect();
+
+                write!(f, "{}", tallies.join(", "))
+            }
+            Value::Roll(roll) => match roll.total() {
+                Ok(total) => write!(f, "{}", total),
+                Err(_) => write!(f, "NaN"),
+            },
+        }
+    }
+}
+
+/// A table of values, where all the keys are either strings or integers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Table {
+    StringKeyed(BTreeMap<String, Value>),
+    IntegerKeyed(BTreeMap<i64, Value>),
+}
+
+/// Writes the table as a map with string keys, as TOML and JSON don't have integer keys.
+/// String keys that would be read back as integers, such as `"10"`, are written with a `#` in front of them instead.
+/// Nested tables are written after the other values, as TOML can't write any more values once a table is written.
+impl Serialize for Table {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entries: Vec<_> = match self {
+            Table::StringKeyed(entries) if !entries.is_empty() && entries.keys().all(|key| is_integer_like(key)) => {
+                entries
+                    .iter()
+                    .map(|(key, value)| (format!("#{}", key), value))
+                    .collect()
+            }
+            Table::StringKeyed(entries) => entries.iter().map(|(key, value)| (key.clone(), value)).collect(),
+            Table::IntegerKeyed(entries) => entries.iter().map(|(key, value)| (key.to_string(), value)).collect(),
+        };
+        let (tables, values): (Vec<_>, Vec<_>) = entries.into_iter().partition(|(_, value)| value.is_table());
+
+        let mut map = serializer.serialize_map(Some(values.len() + tables.len()))?;
+        for (key, value) in values.iter().chain(&tables) {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Reads a map as a table, which is keyed by integers if every one of its keys is an integer, such as `10 = 0`.
+/// A table whose keys all have a `#` in front of them is keyed by the strings after it.
+impl<'de> Deserialize<'de> for Table {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = BTreeMap::<String, Value>::deserialize(deserializer)?;
+
+        if !entries.is_empty() && entries.keys().all(|key| is_integer_key(key)) {
+            let entries = entries
+                .into_iter()
+                .filter_map(|(key, value)| Some((key.parse().ok()?, value)))
+                .collect();
+
+            return Ok(Table::IntegerKeyed(entries));
+        }
+
+        let escaped = |key: &String| key.strip_prefix('#').is_some_and(is_integer_like);
+        if !entries.is_empty() && entries.keys().all(escaped) {
+            let entries = entries
+                .into_iter()
+                .map(|(key, value)| (key[1..].to_owned(), value))
+                .collect();
+
+            return Ok(Table::StringKeyed(entries));
+        }
+
+        Ok(Table::StringKeyed(entries))
+    }
+}
+
+/// Whether a key is written exactly as the integer it parses as, such as `10` but not `010`.
+fn is_integer_key(key: &str) -> bool {
+    key.parse::<i64>().is_ok_and(|integer| integer.to_string() == key)
+}
+
+/// Whether a string key has to be written with a `#` in front of it, so that it isn't read back as an integer key or
+/// as a key that was written that way.
+fn is_integer_like(key: &str) -> bool {
+    is_integer_key(key) || key.strip_prefix('#').is_some_and(is_integer_like)
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<_> = match self {
+            Table::StringKeyed(entries) => entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key, value))
+                .collect(),
+            Table::IntegerKeyed(entries) => entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key, value))
+                .collect(),
+        };
+
+        write!(f, "{{{}}}", entries.join(", "))
+    }
+}
+
+/// The result of the dice operator.
+/// Rolls behave like the total of their counted dice in arithmetic and like a list of those dice when indexed.
+/// Dice which were dropped or rerolled by a modifier are kept in the roll, so they can be shown in a transcript.
+///
+/// Rolls that count successes, such as `5d10cs>=8`, are dice pools and behave like their number of successes instead.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Roll {
+    pub dice: Vec<Die>,
+    #[serde(default)]
+    pub counts_successes: bool,
+    /// The number of each symbol shown by the counted dice, for dice whose faces show symbols.
+    #[serde(default)]
+    pub symbols: BTreeMap<String, i64>,
+}
+
+impl Roll {
+    pub fn new(dice: Vec<Die>) -> Self {
+        let mut symbols = BTreeMap::new();
+
+        for die in dice.iter().filter(|die| die.is_counted()) {
+            for (symbol, count) in &die.symbols {
+                *symbols.entry(symbol.clone()).or_default() += count;
+            }
+        }
+
+        Self {
+            dice,
+            counts_successes: false,
+            symbols,
+        }
+    }
+
+    /// The values of the dice that count towards the roll's total.
+    pub fn values(&self) -> impl Iterator<Item = i64> + '_ {
+        self.dice.iter().filter(|die| die.is_counted()).map(|die| die.value)
+    }
+
+    pub fn total(&self) -> Result<i64, EvaluationError> {
+        if self.counts_successes {
+            return Ok(self.successes());
+        }
+
+        self.values()
+            .try_fold(0i64, |total, die| total.checked_add(die))
+            .ok_or(EvaluationError::Overflow)
+    }
+
+    pub fn successes(&self) -> i64 {
+        self.count_outcomes(Outcome::Success)
+    }
+
+    pub fn failures(&self) -> i64 {
+        self.count_outcomes(Outcome::Failure)
+    }
+
+    fn count_outcomes(&self, outcome: Outcome) -> i64 {
+        self.dice
+            .iter()
+            .filter(|die| die.is_counted() && die.outcome == Some(outcome))
+            .count() as i64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Die {
+    pub value: i64,
+    pub status: DieStatus,
+    #[serde(default)]
+    pub outcome: Option<Outcome>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub symbols: BTreeMap<String, i64>,
+}
+
+impl Die {
+    pub fn new(value: i64, status: DieStatus) -> Self {
+        Self {
+            value,
+            status,
+            outcome: None,
+            symbols: BTreeMap::new(),
+        }
+    }
+
+    pub fn kept(value: i64) -> Self {
+        Self::new(value, DieStatus::Kept)
+    }
+
+    pub fn is_counted(&self) -> bool {
+        matches!(self.status, DieStatus::Kept | DieStatus::Exploded)
+    }
+}
+
+/// Lists each symbol once for every time it's shown, such as `success success advantage`.
+fn symbol_list(symbols: &BTreeMap<String, i64>) -> Vec<&str> {
+    symbols
+        .iter()
+        .flat_map(|(symbol, count)| (0..(*count).max(0)).map(move |_| symbol.as_str()))
+        .collect()
+}
+
+/// Renders the die for a transcript, marking exploded dice with `!`, successes with `s` and failures with `f`,
+/// and putting uncounted dice in parentheses.
+impl fmt::Display for Die {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = match self.outcome {
+            Some(Outcome::Success) => "s",
+            Some(Outcome::Failure) => "f",
+            None => "",
+        };
+
+        let face = if self.symbols.is_empty() {
+            self.value.to_string()
+        } else {
+            symbol_list(&self.symbols).join(" ")
+        };
+
+        match self.status {
+            DieStatus::Kept => write!(f, "{}{}", face, outcome),
+            DieStatus::Exploded => write!(f, "{}!{}", face, outcome),
+            DieStatus::Dropped => write!(f, "({})", face),
+            DieStatus::Rerolled => write!(f, "({}r)", face),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DieStatus {
+    Kept,
+    /// The die met the condition of an exploding modifier and caused another roll.
+    Exploded,
+    /// The die was removed by a keep, drop, advantage or disadvantage modifier.
+    Dropped,
+    /// The die met the condition of a reroll modifier and was replaced by the next die.
+    Rerolled,
+}
+
+/// Whether a die in a dice pool counted as a success or a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tables_round_trip_with_their_keys() {
+        let string_keyed = |keys: &[&str]| {
+            let entries = keys.iter().map(|key| ((*key).to_owned(), Value::Integer(1))).collect();
+            Table::StringKeyed(entries)
+        };
+        let tables = vec![
+            string_keyed(&["1", "2"]),
+            string_keyed(&["#1", "##2"]),
+            string_keyed(&["1", "#2", "a"]),
+            string_keyed(&["01"]),
+            string_keyed(&[]),
+            Table::IntegerKeyed(
+                vec![(-1, Value::Integer(1)), (10, Value::Integer(0))]
+                    .into_iter()
+                    .collect(),
+            ),
+        ];
+
+        for table in tables {
+            let json = serde_json::to_string(&table).unwrap();
+            assert_eq!(serde_json::from_str::<Table>(&json).unwrap(), table, "{}", json);
+        }
+
+        assert_eq!(
+            serde_json::to_string(&string_keyed(&["1", "#2"])).unwrap(),
+            r###"{"##2":1,"#1":1}"###
+        );
+    }
+}
@@ -1,13 +1,20 @@
 use super::{error::DocumentError, Expression, Link};
-use crate::{next_pair, parser::Rule, Symbol};
-use pest::iterators::Pairs;
+use crate::{next_pair, parser::Rule, Location, Symbol};
+use pest::iterators::{Pair, Pairs};
 use std::{
     convert::{TryFrom, TryInto as _},
+    fmt,
     ops::{Deref, DerefMut},
 };
 
 #[derive(Debug)]
-pub enum Span {
+pub struct Span {
+    pub kind: SpanKind,
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub enum SpanKind {
     RawText(String),
     Expression(Expression),
     // TODO: Should there be lib to represent variable names and macro names?
@@ -18,66 +25,180 @@ pub enum Span {
     UnderlineText(SpanList),
     StrikeThroughText(SpanList),
     Link(Link),
+    /// Spans that are repeated once for each of the macro's targets, with `target` referring to the current one.
+    EachTarget(SpanList),
+    /// Spans that are only shown when the condition is true, with the spans of the `else` branch shown otherwise.
+    Conditional {
+        condition: Expression,
+        body: SpanList,
+        otherwise: Option<SpanList>,
+    },
 }
 
 // TODO: Write lib and names for Macro and Variable names.
 
-impl TryFrom<Pairs<'_, Rule>> for Span {
+impl TryFrom<Pair<'_, Rule>> for Span {
     type Error = DocumentError;
 
-    fn try_from(mut span_pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
+    fn try_from(span_pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let location = span_pair.as_span().into();
+        let mut span_pairs = span_pair.into_inner();
         let rule = span_pairs
             .peek()
             .map(|pair| pair.as_rule())
             .unwrap_or_else(|| unreachable!());
 
-        let result = match rule {
+        let kind = match rule {
             Rule::raw_text => {
-                let raw_text = next_pair!(span_pairs => Rule::raw_text).as_str().to_owned();
-                Span::RawText(raw_text)
+                let raw_text = next_pair!(span_pairs => Rule::raw_text).as_str();
+                SpanKind::RawText(unescape(raw_text))
             }
             Rule::macro_name | Rule::variable_name => {
-                let reference = next_pair!(span_pairs => Rule::macro_name | Rule::variable_name)
-                    .into_inner()
-                    .try_into()?;
-                Span::Reference(reference)
+                let reference = next_pair!(span_pairs => Rule::macro_name | Rule::variable_name).try_into()?;
+                SpanKind::Reference(reference)
             }
             Rule::expression => {
                 let expression = span_pairs.try_into()?;
-                Span::Expression(expression)
+                SpanKind::Expression(expression)
             }
             Rule::bold_text => {
                 let bold_text = next_pair!(span_pairs => Rule::bold_text).into_inner().try_into()?;
-                Span::BoldText(bold_text)
+                SpanKind::BoldText(bold_text)
             }
             Rule::italic_text => {
-                let bold_text = next_pair!(span_pairs => Rule::italic_text).into_inner().try_into()?;
-                Span::ItalicText(bold_text)
+                let italic_text = next_pair!(span_pairs => Rule::italic_text).into_inner().try_into()?;
+                SpanKind::ItalicText(italic_text)
             }
             Rule::underline_text => {
-                let bold_text = next_pair!(span_pairs => Rule::underline_text).into_inner().try_into()?;
-                Span::UnderlineText(bold_text)
+                let underline_text = next_pair!(span_pairs => Rule::underline_text).into_inner().try_into()?;
+                SpanKind::UnderlineText(underline_text)
             }
             Rule::strike_through_text => {
-                let bold_text = next_pair!(span_pairs => Rule::strike_through_text)
+                let strike_through_text = next_pair!(span_pairs => Rule::strike_through_text)
                     .into_inner()
                     .try_into()?;
-                Span::StrikeThroughText(bold_text)
+                SpanKind::StrikeThroughText(strike_through_text)
             }
             Rule::macro_link => {
-                let link = next_pair!(span_pairs => Rule::macro_link).into_inner().try_into()?;
-                Span::Link(link)
+                let link = next_pair!(span_pairs => Rule::macro_link).try_into()?;
+                SpanKind::Link(link)
+            }
+            Rule::conditional_block => {
+                let mut conditional_pairs = next_pair!(span_pairs => Rule::conditional_block).into_inner();
+                let condition = next_pair!(conditional_pairs => Rule::dice_expression).try_into()?;
+                let body = next_pair!(conditional_pairs => Rule::conditional_body)
+                    .into_inner()
+                    .try_into()?;
+                let otherwise = conditional_pairs
+                    .next()
+                    .map(|pair| pair.into_inner().try_into())
+                    .transpose()?;
+
+                SpanKind::Conditional {
+                    condition,
+                    body,
+                    otherwise,
+                }
+            }
+            Rule::each_target_block => {
+                let body_pair = next_pair!(span_pairs => Rule::each_target_block);
+                let body = next_pair!(body_pair.into_inner() => Rule::block_body)
+                    .into_inner()
+                    .try_into()?;
+                SpanKind::EachTarget(body)
             }
             _ => unreachable!(),
         };
 
-        Ok(result)
+        Ok(Span { kind, location })
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            SpanKind::RawText(text) => write_escaped(f, text),
+            SpanKind::Expression(expression) => write!(f, "{{% {} %}}", expression),
+            SpanKind::Reference(symbol) => write!(f, "{}", symbol),
+            SpanKind::BoldText(spans) => write!(f, "*{}*", spans),
+            SpanKind::ItalicText(spans) => write!(f, "~{}~", spans),
+            SpanKind::UnderlineText(spans) => write!(f, "_{}_", spans),
+            SpanKind::StrikeThroughText(spans) => write!(f, "-{}-", spans),
+            SpanKind::Link(link) => write!(f, "{}", link),
+            SpanKind::EachTarget(spans) => write!(f, "{{% each target %}}{}{{% end %}}", spans),
+            SpanKind::Conditional {
+                condition,
+                body,
+                otherwise,
+            } => {
+                write!(f, "{{% if {} %}}{}", condition, body)?;
+
+                if let Some(otherwise) = otherwise {
+                    write!(f, "{{% else %}}{}", otherwise)?;
+                }
+
+                write!(f, "{{% end %}}")
+            }
+        }
     }
 }
 
+/// Characters which can be written literally in raw text by prefixing them with a backslash.
+const ESCAPABLE_CHARACTERS: &[char] = &['\\', '*', '~', '_', '-', '#', '$', '[', '{'];
+
+/// Removes the backslash from escaped characters.
+/// Backslashes before any other character are kept as-is.
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next) if c == '\\' && ESCAPABLE_CHARACTERS.contains(&next) => {
+                result.push(next);
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Writes raw text, escaping any character that would otherwise start a span.
+/// Braces only need escaping when they would open an expression.
+fn write_escaped(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let needs_escape = match c {
+            '{' => chars.peek() == Some(&'%'),
+            c => ESCAPABLE_CHARACTERS.contains(&c),
+        };
+
+        if needs_escape {
+            write!(f, "\\")?;
+        }
+
+        write!(f, "{}", c)?;
+    }
+
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct SpanList(Vec<Span>);
 
+impl fmt::Display for SpanList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for span in self.iter() {
+            write!(f, "{}", span)?;
+        }
+
+        Ok(())
+    }
+}
+
 impl Deref for SpanList {
     type Target = [Span];
 
@@ -100,7 +221,7 @@ impl TryFrom<Pairs<'_, Rule>> for SpanList {
 
         for spans_pair in spans_pairs {
             if spans_pair.as_rule() == Rule::macro_span {
-                let span = spans_pair.into_inner().try_into()?;
+                let span = spans_pair.try_into()?;
                 spans.push(span);
             } else {
                 unreachable!()
@@ -1,27 +1,55 @@
-use crate::{error::DocumentError, next_pair, parser::Rule};
-use pest::iterators::Pairs;
-use std::convert::TryFrom;
-
-#[derive(Debug)]
-pub enum Symbol {
-    Variable(String),
-    Macro(String),
+use crate::{error::DocumentError, next_pair, parser::Rule, Location};
+use pest::iterators::Pair;
+use std::{convert::TryFrom, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Macro,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    pub name: String,
+    pub location: Location,
+}
+
+impl Symbol {
+    #[inline]
+    pub fn is_variable(&self) -> bool {
+        self.kind == SymbolKind::Variable
+    }
+
+    #[inline]
+    pub fn is_macro(&self) -> bool {
+        self.kind == SymbolKind::Macro
+    }
 }
 
-impl TryFrom<Pairs<'_, Rule>> for Symbol {
+impl TryFrom<Pair<'_, Rule>> for Symbol {
     type Error = DocumentError;
 
-    fn try_from(mut value: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
-        let result = match value.next().map(|pair| pair.as_rule()) {
-            Some(Rule::macro_name_indicator) => {
-                Symbol::Macro(next_pair!(value => Rule::identifier).as_str().to_owned())
-            }
-            Some(Rule::variable_name_indicator) => {
-                Symbol::Variable(next_pair!(value => Rule::identifier).as_str().to_owned())
-            }
+    fn try_from(symbol_pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let location = symbol_pair.as_span().into();
+        let mut symbol_pairs = symbol_pair.into_inner();
+
+        let kind = match symbol_pairs.next().map(|pair| pair.as_rule()) {
+            Some(Rule::macro_name_indicator) => SymbolKind::Macro,
+            Some(Rule::variable_name_indicator) => SymbolKind::Variable,
             _ => unreachable!(),
         };
+        let name = next_pair!(symbol_pairs => Rule::identifier).as_str().to_owned();
+
+        Ok(Symbol { kind, name, location })
+    }
+}
 
-        Ok(result)
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            SymbolKind::Variable => write!(f, "${}", self.name),
+            SymbolKind::Macro => write!(f, "#{}", self.name),
+        }
     }
 }
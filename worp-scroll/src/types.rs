@@ -0,0 +1,682 @@
+use crate::{
+    error::{DocumentError, TypeError},
+    runtime::{Table, Value},
+    BinaryOperator, Definition, Document, Expression, Literal, Location, PromptType, RollModifier, Span, SpanKind,
+    UnaryOperator, VariableKind,
+};
+use std::{collections::BTreeMap, fmt};
+
+/// The type of a value produced by a DICE expression, as known before the expression is evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Integer,
+    Decimal,
+    Boolean,
+    String,
+    /// A string-keyed table, along with the type of the value stored in each of its keys.
+    Table(BTreeMap<String, Type>),
+    /// An integer-keyed table, along with the type of its values.
+    IntegerTable(Box<Type>),
+    /// A list, along with the type of its values.
+    List(Box<Type>),
+    Roll,
+    /// A value whose type isn't known until the macro is run, which is accepted wherever a value is expected.
+    Any,
+}
+
+impl Type {
+    /// Whether the type can be used where an integer is expected, which includes rolls.
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::Integer | Type::Roll | Type::Any)
+    }
+
+    pub fn is_number(&self) -> bool {
+        self.is_integer() || *self == Type::Decimal
+    }
+
+    /// The type that values of both types share, if there is one.
+    fn unify(&self, other: &Type) -> Option<Type> {
+        let unified = match (self, other) {
+            (Type::Any, _) | (_, Type::Any) => Type::Any,
+            (Type::Integer, Type::Roll) | (Type::Roll, Type::Integer) => Type::Integer,
+            (Type::List(lhs), Type::List(rhs)) => Type::List(Box::new(lhs.unify(rhs)?)),
+            (Type::IntegerTable(lhs), Type::IntegerTable(rhs)) => Type::IntegerTable(Box::new(lhs.unify(rhs)?)),
+            (Type::Table(lhs), Type::Table(rhs)) => {
+                let mut fields = lhs.clone();
+
+                for (key, rhs) in rhs {
+                    let field = match fields.get(key) {
+                        Some(lhs) => lhs.unify(rhs).unwrap_or(Type::Any),
+                        None => rhs.clone(),
+                    };
+                    fields.insert(key.clone(), field);
+                }
+
+                Type::Table(fields)
+            }
+            (lhs, rhs) if lhs == rhs => lhs.clone(),
+            _ => return None,
+        };
+
+        Some(unified)
+    }
+
+    /// Whether the type can be the faces of a die, which may be given as nested lists of integers.
+    fn is_faces(&self) -> bool {
+        match self {
+            Type::List(values) => values.is_faces(),
+            value => value.is_integer(),
+        }
+    }
+
+    /// The type shared by every value, or `None` if the values have different types.
+    fn unify_all<'a>(mut types: impl Iterator<Item = &'a Type>) -> Option<Type> {
+        let first = types.next().cloned().unwrap_or(Type::Any);
+        types.try_fold(first, |unified, value| unified.unify(value))
+    }
+}
+
+/// Finds the type of an existing value, such as a game's `global` table.
+impl From<&Value> for Type {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Integer(_) => Type::Integer,
+            Value::Decimal(_) => Type::Decimal,
+            Value::Boolean(_) => Type::Boolean,
+            Value::String(_) => Type::String,
+            Value::Table(Table::StringKeyed(entries)) => Type::Table(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Type::from(value)))
+                    .collect(),
+            ),
+            Value::Table(Table::IntegerKeyed(entries)) => {
+                let types: Vec<_> = entries.values().map(Type::from).collect();
+                Type::IntegerTable(Box::new(Type::unify_all(types.iter()).unwrap_or(Type::Any)))
+            }
+            Value::List(values) => {
+                let types: Vec<_> = values.iter().map(Type::from).collect();
+                Type::List(Box::new(Type::unify_all(types.iter()).unwrap_or(Type::Any)))
+            }
+            Value::Roll(_) => Type::Roll,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Integer => write!(f, "integer"),
+            Type::Decimal => write!(f, "decimal"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::String => write!(f, "string"),
+            Type::Table(_) => write!(f, "table"),
+            Type::IntegerTable(values) => write!(f, "integer-keyed table of {}", values),
+            Type::List(values) => write!(f, "list of {}", values),
+            Type::Roll => write!(f, "roll"),
+            Type::Any => write!(f, "any"),
+        }
+    }
+}
+
+/// The declared types of the pre-defined tables, such as `global` and `self`, that a document is checked against.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    identifiers: BTreeMap<String, Type>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_identifier(mut self, name: impl Into<String>, identifier_type: Type) -> Self {
+        self.identifiers.insert(name.into(), identifier_type);
+        self
+    }
+
+    #[inline]
+    pub fn identifier(&self, name: &str) -> Option<&Type> {
+        self.identifiers.get(name)
+    }
+}
+
+/// Checks that every expression in a document is used with values of the right type.
+pub(crate) fn type_check(document: &Document, schema: &Schema) -> Vec<DocumentError> {
+    let mut checker = TypeChecker {
+        schema,
+        errors: Vec::new(),
+    };
+
+    let mut main_variables = BTreeMap::new();
+    checker.check_definition(&document.main_macro, &mut main_variables);
+
+    for sub_macro in document.sub_macros.iter() {
+        checker.check_definition(sub_macro, &mut main_variables.clone());
+    }
+
+    checker.errors
+}
+
+struct TypeChecker<'a> {
+    schema: &'a Schema,
+    errors: Vec<DocumentError>,
+}
+
+impl<'a> TypeChecker<'a> {
+    /// Checks a macro definition, adding the type of each variable it declares as they're declared.
+    fn check_definition(&mut self, definition: &'a Definition, variables: &mut BTreeMap<&'a str, Type>) {
+        for variable in definition.variables.iter() {
+            let variable_type = match &variable.kind {
+                VariableKind::Expression(expression) => self.check(expression, variables, variable.location),
+                VariableKind::Prompt(prompt) => match prompt.value_type {
+                    PromptType::Integer => Type::Integer,
+                    PromptType::Decimal => Type::Decimal,
+                    PromptType::Boolean => Type::Boolean,
+                    PromptType::String => Type::String,
+                },
+            };
+            variables.insert(variable.name.name.as_str(), variable_type);
+        }
+
+        self.check_spans(&definition.body, variables);
+    }
+
+    fn check_spans(&mut self, spans: &'a [Span], variables: &BTreeMap<&'a str, Type>) {
+        for span in spans {
+            match &span.kind {
+                SpanKind::Expression(expression) => {
+                    self.check(expression, variables, span.location);
+                }
+                SpanKind::BoldText(spans)
+                | SpanKind::ItalicText(spans)
+                | SpanKind::UnderlineText(spans)
+                | SpanKind::StrikeThroughText(spans)
+                | SpanKind::EachTarget(spans) => self.check_spans(spans, variables),
+                SpanKind::Conditional {
+                    condition,
+                    body,
+                    otherwise,
+                } => {
+                    let condition_type = self.check(condition, variables, span.location);
+                    if let Err(error) = expect_boolean(&condition_type) {
+                        self.errors.push(DocumentError::TypeError {
+                            error,
+                            location: span.location,
+                        });
+                    }
+
+                    self.check_spans(body, variables);
+
+                    if let Some(otherwise) = otherwise {
+                        self.check_spans(otherwise, variables);
+                    }
+                }
+                SpanKind::RawText(_) | SpanKind::Reference(_) | SpanKind::Link(_) => {}
+            }
+        }
+    }
+
+    /// Finds the type of an expression, reporting any mismatches found along the way.
+    /// Expressions with mismatches have the type `Any`, so a single mistake is only reported once.
+    fn check(&mut self, expression: &Expression, variables: &BTreeMap<&str, Type>, location: Location) -> Type {
+        let result = match expression {
+            Expression::Literal(literal) => Ok(match literal {
+                Literal::Integer(_) => Type::Integer,
+                Literal::Decimal(_) => Type::Decimal,
+                Literal::Boolean(_) => Type::Boolean,
+                Literal::String(_) => Type::String,
+            }),
+            Expression::List(items) => {
+                let types: Vec<_> = items.iter().map(|item| self.check(item, variables, location)).collect();
+
+                match Type::unify_all(types.iter()) {
+                    Some(value_type) => Ok(Type::List(Box::new(value_type))),
+                    None if types.iter().all(Type::is_faces) => Ok(Type::List(Box::new(Type::Any))),
+                    None => Err(mixed_list(&types)),
+                }
+            }
+            Expression::Variable(name) => Ok(variables.get(name.as_str()).cloned().unwrap_or(Type::Any)),
+            Expression::Identifier(name) => self
+                .schema
+                .identifier(name)
+                .cloned()
+                .ok_or_else(|| TypeError::UndeclaredIdentifier(name.clone())),
+            Expression::Field { target, field } => field_of(self.check(target, variables, location), field),
+            Expression::Index { target, index } => {
+                let key = match index.as_ref() {
+                    Expression::Literal(Literal::String(key)) => Some(key.as_str()),
+                    _ => None,
+                };
+
+                index_of(
+                    self.check(target, variables, location),
+                    self.check(index, variables, location),
+                    key,
+                )
+            }
+            Expression::Call { function, arguments } => {
+                let arguments: Vec<_> = arguments
+                    .iter()
+                    .map(|item| self.check(item, variables, location))
+                    .collect();
+                call(function, &arguments)
+            }
+            Expression::Unary { operator, operand } => unary(*operator, self.check(operand, variables, location)),
+            Expression::Binary { operator, lhs, rhs } => binary(
+                *operator,
+                self.check(lhs, variables, location),
+                self.check(rhs, variables, location),
+            ),
+            Expression::Range { start, end, .. } => {
+                let (start, end) = (
+                    self.check(start, variables, location),
+                    self.check(end, variables, location),
+                );
+                expect_integer(&start)
+                    .and(expect_integer(&end))
+                    .map(|_| Type::List(Box::new(Type::Integer)))
+            }
+            Expression::Roll {
+                count,
+                sides,
+                modifiers,
+            } => {
+                let count = self.check(count, variables, location);
+                let sides = match sides.as_ref() {
+                    // Names that aren't declared are looked up as named dice when the roll is evaluated.
+                    Expression::Identifier(name) if self.schema.identifier(name).is_none() => Type::Integer,
+                    sides => self.check(sides, variables, location),
+                };
+
+                let mut modifier_result = Ok(());
+                for modifier in modifiers {
+                    for value in modifier_values(modifier) {
+                        let value = self.check(value, variables, location);
+                        modifier_result = modifier_result.and(expect_integer(&value));
+                    }
+                }
+
+                let sides_result = match sides {
+                    Type::List(_) => Ok(()),
+                    sides if sides.is_integer() => Ok(()),
+                    sides => Err(TypeError::TypeMismatch {
+                        expected: "integer or list",
+                        found: sides,
+                    }),
+                };
+
+                expect_integer(&count)
+                    .and(sides_result)
+                    .and(modifier_result)
+                    .map(|_| Type::Roll)
+            }
+            Expression::Assign {
+                operator,
+                target,
+                value,
+            } => match target.as_ref() {
+                Expression::Field { target: token, field } => {
+                    let table = self.check(token, variables, location);
+                    let value = self.check(value, variables, location);
+                    let value = match operator.binary_operator() {
+                        Some(operator) => {
+                            field_of(table.clone(), field).and_then(|previous| binary(operator, previous, value))
+                        }
+                        None => Ok(value),
+                    };
+
+                    // Only tokens can be assigned to, which are the tables with an id.
+                    let token = match &table {
+                        Type::Table(fields) => match fields.get("id") {
+                            Some(Type::String | Type::Any) => Ok(()),
+                            _ => Err(TypeError::InvalidAssignment(target.to_string())),
+                        },
+                        _ => expect(&table, "table", table == Type::Any),
+                    };
+
+                    // Rolls are stored as their total, as attributes only keep numbers.
+                    token
+                        .and(value)
+                        .and_then(|value| expect_number(&value).map(|_| value))
+                        .map(|value| if value == Type::Roll { Type::Integer } else { value })
+                }
+                target => Err(TypeError::InvalidAssignment(target.to_string())),
+            },
+        };
+
+        result.unwrap_or_else(|error| {
+            self.errors.push(DocumentError::TypeError { error, location });
+            Type::Any
+        })
+    }
+}
+
+fn mixed_list(types: &[Type]) -> TypeError {
+    let first = types
+        .iter()
+        .find(|value| **value != Type::Any)
+        .cloned()
+        .unwrap_or(Type::Any);
+    let second = types
+        .iter()
+        .find(|value| first.unify(value).is_none())
+        .cloned()
+        .unwrap_or(Type::Any);
+
+    TypeError::MixedList { first, second }
+}
+
+fn expect(found: &Type, expected: &'static str, matches: bool) -> Result<(), TypeError> {
+    if matches {
+        Ok(())
+    } else {
+        Err(TypeError::TypeMismatch {
+            expected,
+            found: found.clone(),
+        })
+    }
+}
+
+fn expect_integer(found: &Type) -> Result<(), TypeError> {
+    expect(found, "integer", found.is_integer())
+}
+
+fn expect_boolean(found: &Type) -> Result<(), TypeError> {
+    expect(found, "boolean", matches!(found, Type::Boolean | Type::Any))
+}
+
+fn expect_number(found: &Type) -> Result<(), TypeError> {
+    expect(found, "number", found.is_number())
+}
+
+/// The expressions of a roll modifier, all of which must be integers.
+fn modifier_values(modifier: &RollModifier) -> Vec<&Expression> {
+    match modifier {
+        RollModifier::Keep { count, .. } | RollModifier::Drop { count, .. } => count.iter().collect(),
+        RollModifier::Advantage | RollModifier::Disadvantage => Vec::new(),
+        RollModifier::Reroll { condition, .. } => vec![&condition.value],
+        RollModifier::Explode { condition, .. }
+        | RollModifier::CountSuccesses { condition }
+        | RollModifier::CountFailures { condition } => condition.iter().map(|condition| &condition.value).collect(),
+    }
+}
+
+fn field_of(target: Type, field: &str) -> Result<Type, TypeError> {
+    match target {
+        Type::Table(fields) => fields
+            .get(field)
+            .cloned()
+            .ok_or_else(|| TypeError::UnknownKey(field.to_owned())),
+        Type::Roll => match field {
+            "total" | "successes" | "failures" => Ok(Type::Integer),
+            "dice" => Ok(Type::List(Box::new(Type::Integer))),
+            "symbols" => Ok(Type::Any),
+            _ => Err(TypeError::UnknownKey(field.to_owned())),
+        },
+        Type::Any => Ok(Type::Any),
+        target => Err(TypeError::TypeMismatch {
+            expected: "table",
+            found: target,
+        }),
+    }
+}
+
+fn index_of(target: Type, index: Type, key: Option<&str>) -> Result<Type, TypeError> {
+    match target {
+        Type::Table(fields) => {
+            expect(&index, "string", matches!(index, Type::String | Type::Any))?;
+
+            match key {
+                Some(key) => field_of(Type::Table(fields), key),
+                None => Ok(Type::unify_all(fields.values()).unwrap_or(Type::Any)),
+            }
+        }
+        Type::IntegerTable(values) | Type::List(values) => expect_integer(&index).map(|_| *values),
+        Type::Roll => expect_integer(&index).map(|_| Type::Integer),
+        Type::Any => Ok(Type::Any),
+        target => Err(TypeError::TypeMismatch {
+            expected: "table or list",
+            found: target,
+        }),
+    }
+}
+
+/// Checks the arguments of a call to one of the built-in functions, mirroring the checks made when it's called.
+fn call(function: &str, arguments: &[Type]) -> Result<Type, TypeError> {
+    let expect_arguments = |expected: usize| {
+        if arguments.len() == expected {
+            Ok(())
+        } else {
+            Err(TypeError::ArgumentCount {
+                function: function.to_owned(),
+                expected,
+                found: arguments.len(),
+            })
+        }
+    };
+    let expect_integers = |list: &Type| match list {
+        Type::List(values) => expect_integer(values),
+        list => expect(list, "list", matches!(list, Type::Roll | Type::Any)),
+    };
+
+    match function {
+        "sum" => {
+            expect_arguments(1)?;
+            expect_integers(&arguments[0]).map(|_| Type::Integer)
+        }
+        "len" => {
+            expect_arguments(1)?;
+            let list = &arguments[0];
+
+            expect(
+                list,
+                "list",
+                matches!(list, Type::List(_) | Type::Roll | Type::String | Type::Any),
+            )
+            .map(|_| Type::Integer)
+        }
+        "min" | "max" => {
+            match arguments {
+                [list] => expect_integers(list)?,
+                arguments => arguments.iter().try_for_each(expect_integer)?,
+            }
+
+            Ok(Type::Integer)
+        }
+        "abs" => {
+            expect_arguments(1)?;
+
+            match &arguments[0] {
+                Type::Decimal => Ok(Type::Decimal),
+                Type::Any => Ok(Type::Any),
+                value => expect_integer(value).map(|_| Type::Integer),
+            }
+        }
+        "floor" | "ceil" | "round" => {
+            expect_arguments(1)?;
+            expect_number(&arguments[0]).map(|_| Type::Integer)
+        }
+        _ => Err(TypeError::UndefinedFunction(function.to_owned())),
+    }
+}
+
+fn unary(operator: UnaryOperator, operand: Type) -> Result<Type, TypeError> {
+    match operator {
+        UnaryOperator::Not => expect_boolean(&operand).map(|_| Type::Boolean),
+        UnaryOperator::Negate => match operand {
+            Type::Decimal | Type::Any => Ok(operand),
+            operand => expect_integer(&operand).map(|_| Type::Integer),
+        },
+    }
+}
+
+fn binary(operator: BinaryOperator, lhs: Type, rhs: Type) -> Result<Type, TypeError> {
+    match operator {
+        BinaryOperator::Equal | BinaryOperator::NotEqual => Ok(Type::Boolean),
+        BinaryOperator::LessThan
+        | BinaryOperator::LessThanOrEqual
+        | BinaryOperator::GreaterThan
+        | BinaryOperator::GreaterThanOrEqual => match (&lhs, &rhs) {
+            (Type::String, Type::String | Type::Any) | (Type::Any, Type::String) => Ok(Type::Boolean),
+            _ => expect_number(&lhs).and(expect_number(&rhs)).map(|_| Type::Boolean),
+        },
+        BinaryOperator::And | BinaryOperator::Or => {
+            expect_boolean(&lhs).and(expect_boolean(&rhs)).map(|_| Type::Boolean)
+        }
+        BinaryOperator::Add if lhs == Type::String => Ok(Type::String),
+        BinaryOperator::Add if lhs == Type::Any && rhs == Type::String => Ok(Type::Any),
+        BinaryOperator::Add
+        | BinaryOperator::Subtract
+        | BinaryOperator::Multiply
+        | BinaryOperator::Divide
+        | BinaryOperator::Remainder => {
+            expect_number(&lhs)?;
+            expect_number(&rhs)?;
+
+            match (lhs, rhs) {
+                (Type::Decimal, _) | (_, Type::Decimal) => Ok(Type::Decimal),
+                (Type::Any, _) | (_, Type::Any) => Ok(Type::Any),
+                _ => Ok(Type::Integer),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::{DocumentError, TypeError};
+
+    fn type_check(input: &str) -> Vec<TypeError> {
+        let mut abilities = BTreeMap::new();
+        abilities.insert("strength".to_owned(), Type::Integer);
+        abilities.insert("charisma".to_owned(), Type::Integer);
+        abilities.insert("name".to_owned(), Type::String);
+        abilities.insert("id".to_owned(), Type::String);
+
+        let mut global = BTreeMap::new();
+        global.insert("ability_mods".to_owned(), Type::IntegerTable(Box::new(Type::Integer)));
+        global.insert("critical".to_owned(), Type::Boolean);
+
+        let schema = Schema::new()
+            .with_identifier("global", Type::Table(global))
+            .with_identifier("self", Type::Table(abilities));
+
+        Document::try_from_str(input)
+            .unwrap()
+            .type_check(&schema)
+            .err()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|error| match error {
+                DocumentError::TypeError { error, .. } => error,
+                error => panic!("expected a type error, found {:?}", error),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn type_check_accepts_test_data() {
+        let inputs = &[
+            include_str!("../test/data/long_sword_basic_attack.txt"),
+            include_str!("../test/data/long_sword_multiple_attack.txt"),
+            include_str!("../test/data/eblast.txt"),
+        ];
+
+        for input in inputs {
+            assert_eq!(type_check(input), Vec::new());
+        }
+    }
+
+    #[test]
+    fn type_check_accepts_valid_expressions() {
+        let inputs = &[
+            "{% 1d20 + global.ability_mods[self.strength] %}",
+            "$x := {% 2.5 %}\n\n{% floor($x * 2) + 1d[1, [3, 5], 2=..=4] %}",
+            "{% self[\"name\"] + \": \" + max(4d6kh3, 3d6) %}",
+            "$name := {% self.name %}\n\n{% $name + 1 %}",
+            "{% 2dF + 1d% + 3dBoost %}",
+            "{% (1d20).total >= 10 && !global.critical %}",
+            "{% self.strength -= 1d4 %} {% self.exhaustion = 1 %}",
+            "{% if self.strength > 10 %}*{% self.name %}*{% else %}weak{% end %}",
+            "$k := {% \"name\" %}\n\n{% self[$k] + \" gp\" %}",
+        ];
+
+        for input in inputs {
+            assert_eq!(type_check(input), Vec::new(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn type_check_reports_mismatches() {
+        assert_eq!(
+            type_check("{% 6d\"abc\" %}"),
+            vec![TypeError::TypeMismatch {
+                expected: "integer or list",
+                found: Type::String,
+            }]
+        );
+        assert_eq!(
+            type_check("$name := {% self.name %}\n\n{% 1 + $name %} {% 1d6kh($name) %}"),
+            vec![
+                TypeError::TypeMismatch {
+                    expected: "number",
+                    found: Type::String,
+                },
+                TypeError::TypeMismatch {
+                    expected: "integer",
+                    found: Type::String,
+                },
+            ]
+        );
+        assert_eq!(
+            type_check("{% [1, \"two\"] %}"),
+            vec![TypeError::MixedList {
+                first: Type::Integer,
+                second: Type::String,
+            }]
+        );
+        assert_eq!(
+            type_check("{% global.ability_mods[\"strength\"] %}{% self.dexterity %}{% token.name %}"),
+            vec![
+                TypeError::TypeMismatch {
+                    expected: "integer",
+                    found: Type::String,
+                },
+                TypeError::UnknownKey("dexterity".to_owned()),
+                TypeError::UndeclaredIdentifier("token".to_owned()),
+            ]
+        );
+        assert_eq!(
+            type_check("{% if 1d20 %}{% 1 + \"a\" %}{% end %}"),
+            vec![
+                TypeError::TypeMismatch {
+                    expected: "boolean",
+                    found: Type::Roll,
+                },
+                TypeError::TypeMismatch {
+                    expected: "number",
+                    found: Type::String,
+                },
+            ]
+        );
+        assert_eq!(
+            type_check(
+                "{% self.name -= 1 %}{% global.ability_mods[1] = 2 %}{% global.critical = 1 %}{% self.hp = true %}"
+            ),
+            vec![
+                TypeError::TypeMismatch {
+                    expected: "number",
+                    found: Type::String,
+                },
+                TypeError::InvalidAssignment("global.ability_mods[1]".to_owned()),
+                TypeError::InvalidAssignment("global.critical".to_owned()),
+                TypeError::TypeMismatch {
+                    expected: "number",
+                    found: Type::Boolean,
+                },
+            ]
+        );
+    }
+}
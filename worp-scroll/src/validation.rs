@@ -0,0 +1,276 @@
+use crate::{
+    error::DocumentError, Definition, Document, Expression, LinkTarget, Location, Prompt, Span, SpanKind, Symbol,
+    VariableKind,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Checks a parsed document for problems that can't be caught by the grammar alone.
+pub(crate) fn validate(document: &Document) -> Vec<DocumentError> {
+    let mut validator = Validator {
+        document,
+        sub_macro_variables: BTreeMap::new(),
+        current_sub_macro: None,
+        errors: Vec::new(),
+    };
+
+    validator.validate_sub_macro_names();
+
+    for sub_macro in document.sub_macros.iter() {
+        let sub_macro_name = sub_macro
+            .name
+            .as_ref()
+            .map(|name| name.name.as_str())
+            .unwrap_or_default();
+
+        for variable in sub_macro.variables.iter() {
+            validator
+                .sub_macro_variables
+                .entry(variable.name.name.as_str())
+                .or_insert(sub_macro_name);
+        }
+    }
+
+    let mut main_scope = BTreeSet::new();
+    validator.validate_definition(&document.main_macro, &mut main_scope);
+
+    for sub_macro in document.sub_macros.iter() {
+        validator.validate_definition(sub_macro, &mut main_scope.clone());
+    }
+
+    let mut errors = validator.errors;
+    errors.sort_by_key(|error| match error {
+        DocumentError::UnknownLinkTarget { location, .. }
+        | DocumentError::UndefinedVariable { location, .. }
+        | DocumentError::DuplicateSubMacro { location, .. }
+        | DocumentError::OutOfScopeVariable { location, .. }
+        | DocumentError::InvalidPromptValue { location, .. } => location.start,
+        _ => 0,
+    });
+
+    errors
+}
+
+struct Validator<'a> {
+    document: &'a Document,
+    sub_macro_variables: BTreeMap<&'a str, &'a str>,
+    current_sub_macro: Option<&'a str>,
+    errors: Vec<DocumentError>,
+}
+
+impl<'a> Validator<'a> {
+    fn validate_sub_macro_names(&mut self) {
+        let mut seen = BTreeMap::new();
+
+        for symbol in self
+            .document
+            .sub_macros
+            .iter()
+            .filter_map(|sub_macro| sub_macro.name.as_ref())
+        {
+            if let Some(previous) = seen.insert(symbol.name.as_str(), symbol.location) {
+                self.errors.push(DocumentError::DuplicateSubMacro {
+                    name: symbol.name.clone(),
+                    location: symbol.location,
+                    previous,
+                });
+            }
+        }
+    }
+
+    /// Validates a macro definition, adding the variables it declares to the scope as they're declared.
+    fn validate_definition(&mut self, definition: &'a Definition, scope: &mut BTreeSet<&'a str>) {
+        self.current_sub_macro = definition.name.as_ref().map(|name| name.name.as_str());
+
+        for variable in definition.variables.iter() {
+            match &variable.kind {
+                VariableKind::Expression(expression) => expression.visit(&mut |expression| {
+                    if let Expression::Variable(name) = expression {
+                        self.validate_variable(name, scope, variable.location);
+                    }
+                }),
+                VariableKind::Prompt(prompt) => self.validate_prompt(&variable.name.name, prompt, variable.location),
+            }
+
+            scope.insert(variable.name.name.as_str());
+        }
+
+        self.validate_spans(&definition.body, scope);
+    }
+
+    /// Checks that a prompt's choices are of its type and that its default is one of the answers it accepts.
+    fn validate_prompt(&mut self, name: &str, prompt: &Prompt, location: Location) {
+        let invalid_choices = prompt
+            .choices
+            .iter()
+            .filter(|choice| !prompt.value_type.accepts(choice));
+        let invalid_default = prompt.default.iter().filter(|default| !prompt.accepts(default));
+
+        for value in invalid_choices.chain(invalid_default) {
+            self.errors.push(DocumentError::InvalidPromptValue {
+                name: name.to_owned(),
+                value: value.to_string(),
+                location,
+            });
+        }
+    }
+
+    fn validate_spans(&mut self, spans: &'a [Span], scope: &BTreeSet<&'a str>) {
+        for span in spans {
+            match &span.kind {
+                SpanKind::RawText(_) => {}
+                SpanKind::Expression(expression) => expression.visit(&mut |expression| {
+                    if let Expression::Variable(name) = expression {
+                        self.validate_variable(name, scope, span.location);
+                    }
+                }),
+                SpanKind::Reference(symbol) if symbol.is_variable() => {
+                    self.validate_variable(&symbol.name, scope, symbol.location)
+                }
+                SpanKind::Reference(_) => {}
+                SpanKind::BoldText(spans)
+                | SpanKind::ItalicText(spans)
+                | SpanKind::UnderlineText(spans)
+                | SpanKind::StrikeThroughText(spans)
+                | SpanKind::EachTarget(spans) => self.validate_spans(spans, scope),
+                SpanKind::Conditional {
+                    condition,
+                    body,
+                    otherwise,
+                } => {
+                    condition.visit(&mut |expression| {
+                        if let Expression::Variable(name) = expression {
+                            self.validate_variable(name, scope, span.location);
+                        }
+                    });
+                    self.validate_spans(body, scope);
+
+                    if let Some(otherwise) = otherwise {
+                        self.validate_spans(otherwise, scope);
+                    }
+                }
+                SpanKind::Link(link) => match &link.target {
+                    LinkTarget::Target(target) => self.validate_link_target(target),
+                    LinkTarget::TargetList(targets) => {
+                        for labeled_target in targets.iter() {
+                            self.validate_link_target(&labeled_target.target);
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    fn validate_variable(&mut self, name: &str, scope: &BTreeSet<&'a str>, location: Location) {
+        if scope.contains(name) {
+            return;
+        }
+
+        let error = match self.sub_macro_variables.get(name) {
+            Some(sub_macro) if Some(*sub_macro) != self.current_sub_macro => DocumentError::OutOfScopeVariable {
+                name: name.to_owned(),
+                sub_macro: (*sub_macro).to_owned(),
+                location,
+            },
+            _ => DocumentError::UndefinedVariable {
+                name: name.to_owned(),
+                location,
+            },
+        };
+
+        self.errors.push(error);
+    }
+
+    fn validate_link_target(&mut self, target: &Symbol) {
+        if self.document.sub_macro(&target.name).is_none() {
+            self.errors.push(DocumentError::UnknownLinkTarget {
+                name: target.name.clone(),
+                location: target.location,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::DocumentError, Document};
+
+    fn validate(input: &str) -> Vec<DocumentError> {
+        Document::try_from_str(input)
+            .unwrap()
+            .validate()
+            .err()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn validate_accepts_test_data() {
+        let inputs = &[
+            include_str!("../test/data/long_sword_basic_attack.txt"),
+            include_str!("../test/data/long_sword_multiple_attack.txt"),
+            include_str!("../test/data/eblast.txt"),
+        ];
+
+        for input in inputs {
+            assert!(validate(input).is_empty());
+        }
+    }
+
+    #[test]
+    fn validate_reports_unknown_link_targets() {
+        let errors = validate("[Pick](\"A\": #first, \"B\": #second)\n\n== #first ==\nfirst");
+
+        assert!(matches!(
+            &errors[..],
+            [DocumentError::UnknownLinkTarget { name, location }] if name == "second" && location.column == 26
+        ));
+    }
+
+    #[test]
+    fn validate_reports_undefined_variables() {
+        let errors = validate("$a := {% $b + 1 %}\n$b := {% 2 %}\n\n*$charisma_mod*");
+
+        assert!(matches!(
+            &errors[..],
+            [
+                DocumentError::UndefinedVariable { name: first, location: first_location },
+                DocumentError::UndefinedVariable { name: second, location: second_location },
+            ] if first == "b" && first_location.line == 1 && second == "charisma_mod" && second_location.line == 4
+        ));
+    }
+
+    #[test]
+    fn validate_reports_invalid_prompt_values() {
+        let errors =
+            validate("$a := prompt \"A\": integer [1, 2.5] = 3\n$b := prompt \"B\": decimal [1, 2.5] = 1\n\n$a $b");
+
+        assert!(matches!(
+            &errors[..],
+            [
+                DocumentError::InvalidPromptValue { name: first, value: first_value, .. },
+                DocumentError::InvalidPromptValue { name: second, value: second_value, .. },
+            ] if first == "a" && first_value == "2.5" && second == "a" && second_value == "3"
+        ));
+    }
+
+    #[test]
+    fn validate_reports_duplicate_sub_macros() {
+        let errors = validate("main\n\n== #roll ==\nfirst\n\n== #roll ==\nsecond");
+
+        assert!(matches!(
+            &errors[..],
+            [DocumentError::DuplicateSubMacro { name, location, previous }]
+                if name == "roll" && location.line == 6 && previous.line == 3
+        ));
+    }
+
+    #[test]
+    fn validate_reports_variables_from_sibling_sub_macros() {
+        let errors = validate("$x := {% 1 %}\n\nmain $x\n\n== #a ==\n$y := {% $x %}\n\n$y\n\n== #b ==\n$x $y");
+
+        assert!(matches!(
+            &errors[..],
+            [DocumentError::OutOfScopeVariable { name, sub_macro, location }]
+                if name == "y" && sub_macro == "a" && location.line == 11
+        ));
+    }
+}
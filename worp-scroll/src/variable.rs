@@ -1,27 +1,175 @@
-use super::{error::DocumentError, Expression};
-use crate::{next_pair, parser::Rule, Symbol};
-use pest::iterators::Pairs;
+use super::{error::DocumentError, Expression, Literal};
+use crate::{next_pair, parser::Rule, Location, Symbol};
+use pest::iterators::{Pair, Pairs};
+use serde::{Deserialize, Serialize};
 use std::{
     convert::{TryFrom, TryInto as _},
+    fmt,
     ops::{Deref, DerefMut},
 };
 
 #[derive(Debug)]
 pub struct Variable {
     pub name: Symbol,
-    pub expression: Expression,
+    pub kind: VariableKind,
+    pub location: Location,
 }
 
-impl TryFrom<Pairs<'_, Rule>> for Variable {
+#[derive(Debug)]
+pub enum VariableKind {
+    /// A variable whose value is computed by evaluating an expression, such as `$x := {% 1d20 %}`.
+    Expression(Expression),
+    /// A variable whose value is asked for when the macro is run, such as `$bonus := prompt "Bonus": integer = 0`.
+    Prompt(Prompt),
+}
+
+/// A question asked of the player running a macro, whose answer becomes the value of a variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prompt {
+    pub label: String,
+    pub value_type: PromptType,
+    /// The only answers allowed, or any answer of the right type if empty.
+    pub choices: Vec<Literal>,
+    pub default: Option<Literal>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PromptType {
+    Integer,
+    Decimal,
+    Boolean,
+    String,
+}
+
+impl Prompt {
+    /// Whether the value is a valid answer, being of the prompt's type and one of its choices, if it has any.
+    pub fn accepts(&self, value: &Literal) -> bool {
+        self.value_type.accepts(value) && (self.choices.is_empty() || self.choices.contains(value))
+    }
+}
+
+impl PromptType {
+    /// Whether a value is of this type, with integers also accepted as decimals.
+    pub fn accepts(self, value: &Literal) -> bool {
+        matches!(
+            (self, value),
+            (PromptType::Integer, Literal::Integer(_))
+                | (PromptType::Decimal, Literal::Decimal(_))
+                | (PromptType::Decimal, Literal::Integer(_))
+                | (PromptType::Boolean, Literal::Boolean(_))
+                | (PromptType::String, Literal::String(_))
+        )
+    }
+}
+
+impl TryFrom<Pair<'_, Rule>> for Variable {
+    type Error = DocumentError;
+
+    fn try_from(variable_pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let location = variable_pair.as_span().into();
+        let mut variable_pairs = variable_pair.into_inner();
+        let name = next_pair!(variable_pairs => Rule::variable_name).try_into()?;
+        let kind = match variable_pairs.peek().map(|pair| pair.as_rule()) {
+            Some(Rule::prompt) => VariableKind::Prompt(next_pair!(variable_pairs => Rule::prompt).try_into()?),
+            _ => VariableKind::Expression(variable_pairs.try_into()?),
+        };
+
+        Ok(Variable { name, kind, location })
+    }
+}
+
+impl TryFrom<Pair<'_, Rule>> for Prompt {
     type Error = DocumentError;
 
-    fn try_from(mut variable_pairs: Pairs<'_, Rule>) -> Result<Self, Self::Error> {
-        let name = next_pair!(variable_pairs => Rule::variable_name)
-            .into_inner()
-            .try_into()?;
-        let expression = variable_pairs.try_into()?;
+    fn try_from(prompt_pair: Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        let mut prompt_pairs = prompt_pair.into_inner();
+        let label = match Expression::try_from(next_pair!(prompt_pairs => Rule::string_literal))? {
+            Expression::Literal(Literal::String(label)) => label,
+            _ => unreachable!(),
+        };
+        let value_type = match next_pair!(prompt_pairs => Rule::prompt_type).as_str() {
+            "integer" => PromptType::Integer,
+            "decimal" => PromptType::Decimal,
+            "boolean" => PromptType::Boolean,
+            _ => PromptType::String,
+        };
+        let mut prompt = Prompt {
+            label,
+            value_type,
+            choices: Vec::new(),
+            default: None,
+        };
+
+        for pair in prompt_pairs {
+            match pair.as_rule() {
+                Rule::prompt_choices => {
+                    prompt.choices = pair.into_inner().map(prompt_value).collect::<Result<_, _>>()?;
+                }
+                Rule::prompt_default => {
+                    prompt.default = Some(prompt_value(next_pair!(pair.into_inner() => Rule::prompt_value))?);
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(prompt)
+    }
+}
+
+fn prompt_value(value_pair: Pair<'_, Rule>) -> Result<Literal, DocumentError> {
+    let mut value_pairs = value_pair.into_inner().peekable();
+    let negative = value_pairs
+        .next_if(|pair| pair.as_rule() == Rule::prompt_negative)
+        .is_some();
+    let literal = match Expression::try_from(value_pairs.next().unwrap_or_else(|| unreachable!()))? {
+        Expression::Literal(literal) => literal,
+        _ => unreachable!(),
+    };
+
+    Ok(match literal {
+        Literal::Integer(value) if negative => Literal::Integer(-value),
+        Literal::Decimal(value) if negative => Literal::Decimal(-value),
+        literal => literal,
+    })
+}
+
+/// Renders the value of the variable as it's written after the `:=` of its declaration.
+impl fmt::Display for VariableKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VariableKind::Expression(expression) => write!(f, "{{% {} %}}", expression),
+            VariableKind::Prompt(prompt) => write!(f, "{}", prompt),
+        }
+    }
+}
+
+impl fmt::Display for Prompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "prompt {}: {}", Literal::String(self.label.clone()), self.value_type)?;
+
+        if !self.choices.is_empty() {
+            let choices: Vec<_> = self.choices.iter().map(Literal::to_string).collect();
+            write!(f, " [{}]", choices.join(", "))?;
+        }
+
+        if let Some(default) = &self.default {
+            write!(f, " = {}", default)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for PromptType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PromptType::Integer => "integer",
+            PromptType::Decimal => "decimal",
+            PromptType::Boolean => "boolean",
+            PromptType::String => "string",
+        };
 
-        Ok(Variable { name, expression })
+        write!(f, "{}", name)
     }
 }
 
@@ -49,7 +197,7 @@ impl TryFrom<Pairs<'_, Rule>> for VariableList {
         let mut variables = Vec::new();
 
         for variable_pair in variables_pairs {
-            let variable = variable_pair.into_inner().try_into()?;
+            let variable = variable_pair.try_into()?;
             variables.push(variable);
         }
 